@@ -0,0 +1,86 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::path::Path;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use hawkbit::ddi::{
+    ActionState, Agent, Client, DownloadedArtifact, Error, Outcome, Update, UpdateHandler,
+};
+use serde::Serialize;
+use structopt::StructOpt;
+use tokio_util::sync::CancellationToken;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "agent example")]
+struct Opt {
+    url: String,
+    controller: String,
+    key: String,
+    #[structopt(short, long, default_value = "DEFAULT")]
+    tenant: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ConfigData {
+    #[serde(rename = "HwRevision")]
+    hw_revision: String,
+}
+
+struct Handler;
+
+#[async_trait]
+impl UpdateHandler for Handler {
+    type Config = ConfigData;
+
+    async fn on_config_request(&self) -> ConfigData {
+        println!("Uploading config data");
+        ConfigData {
+            hw_revision: "1.0".to_string(),
+        }
+    }
+
+    async fn on_update(&self, update: &Update, artifacts: Vec<DownloadedArtifact>) -> Outcome {
+        println!("Installing update");
+        dbg!(update);
+        dbg!(&artifacts);
+
+        Outcome::Success(vec![])
+    }
+
+    async fn on_cancel(&self, action_id: &str) -> Outcome {
+        println!("Action cancelled: {}", action_id);
+        Outcome::Success(vec![])
+    }
+
+    async fn on_resume(&self, state: &ActionState) -> Outcome {
+        println!("Update {} completed after restart", state.id());
+        Outcome::Success(vec![])
+    }
+
+    async fn on_error(&self, error: &Error) {
+        println!("Error: {}", error);
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opt = Opt::from_args();
+
+    let ddi = Client::new(&opt.url, &opt.tenant, &opt.controller, &opt.key)?;
+    let agent =
+        Agent::new(ddi, Handler, Path::new("./download/")).state_file(Path::new("./action.json"));
+
+    let token = CancellationToken::new();
+    let stop = token.clone();
+    tokio::spawn(async move {
+        tokio::signal::ctrl_c().await.ok();
+        println!("Stopping");
+        stop.cancel();
+    });
+
+    agent.run(token).await;
+
+    Ok(())
+}
@@ -6,28 +6,62 @@
 //! This module provides API for devices to poll their hawkBit server, upload their configuration
 //! and download updates.
 //!
-//! Devices would typically create a [`Client`] using [`Client::new`]
+//! Devices would typically create a [`Client`] using [`Client::new`], or [`ClientBuilder`]
+//! to customize its HTTP settings,
 //! and would then regularly call [`Client::poll`] checking for updates.
 //!
-//! See `examples/polling.rs` demonstrating how to use it.
+//! Alternatively, devices can implement the [`UpdateHandler`] trait and let an [`Agent`]
+//! poll the server and report the outcome of each request.
+//!
+//! See `examples/polling.rs` and `examples/agent.rs` demonstrating how to use them.
 
 // FIXME: set link to hawbit/examples/polling.rs once we have the final public repo
 
+mod action_state;
+mod agent;
 mod cancel_action;
 mod client;
 mod common;
 mod config_data;
+mod confirmation;
+#[cfg(feature = "decompress")]
+mod decompress;
 mod deployment_base;
+mod download;
 mod feedback;
+mod http;
+mod installed_base;
+mod journal;
 mod poll;
+mod rate_limit;
+mod retry;
+#[cfg(feature = "signature")]
+mod signature;
+mod software_module;
 
+pub use action_state::{ActionState, Phase, ResumedAction};
+pub use agent::{Agent, Outcome, UpdateHandler};
 pub use cancel_action::CancelAction;
-pub use client::{Client, Error};
+pub use client::{Auth, Client, ClientBuilder, Error, ServerError};
 pub use common::{Execution, Finished};
 pub use config_data::{ConfigRequest, Mode};
+pub use confirmation::{
+    AutoConfirm, Confirmation, ConfirmationFeedbackBuilder, ConfirmationRequest,
+    ConfirmationResponse,
+};
+#[cfg(feature = "decompress")]
+pub use decompress::{Compression, Decompression};
 #[cfg(feature = "hash-digest")]
 pub use deployment_base::ChecksumType;
 pub use deployment_base::{
-    Artifact, Chunk, DownloadedArtifact, MaintenanceWindow, Type, Update, UpdatePreFetch,
+    ActionHistory, Artifact, Chunk, DownloadedArtifact, MaintenanceWindow, Type, Update,
+    UpdatePreFetch,
 };
+pub use download::{DownloadOptions, DownloadProgress, FileNaming, LinkPolicy, VerificationPolicy};
+pub use feedback::{FeedbackBuilder, Progress};
+pub use installed_base::{Installed, InstalledPreFetch};
 pub use poll::Reply;
+pub use retry::RetryPolicy;
+#[cfg(feature = "signature")]
+pub use signature::{TrustedKeys, VerifiedSignature};
+pub use software_module::SoftwareModule;
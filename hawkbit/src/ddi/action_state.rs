@@ -0,0 +1,169 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// Persistent state of an update action, used to report its result after a reboot
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ddi::client::Error;
+use crate::ddi::common::{send_feedback_to, write_file_atomic, Execution, Finished};
+use crate::ddi::feedback::FeedbackBuilder;
+use crate::ddi::http::HttpClient;
+
+/// Phase an update action was in when its [`ActionState`] was saved.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// The artifacts of the update are being downloaded.
+    Downloading,
+    /// The update is being installed, which may involve rebooting the device.
+    Installing,
+}
+
+/// State of an update action which can be saved to disk and used to report
+/// its result once the device restarted, see [`Update::action_state`](crate::ddi::Update::action_state)
+/// and [`Client::resume`](crate::ddi::Client::resume).
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use hawkbit::ddi::{ActionState, Client, Error, Execution, Finished, Phase, Update};
+///
+/// const STATE: &str = "/var/lib/my-device/action.json";
+///
+/// // before rebooting to install the update
+/// async fn install(update: &Update) -> Result<(), Error> {
+///     update.action_state(Phase::Installing)?.save(Path::new(STATE)).await?;
+///     // install and reboot
+///     Ok(())
+/// }
+///
+/// // once the device restarted
+/// async fn resume(ddi: &Client) -> Result<(), Error> {
+///     let path = Path::new(STATE);
+///     if let Some(state) = ActionState::load(path).await? {
+///         ddi.resume(state)
+///             .send_feedback(Execution::Closed, Finished::Success, vec![])
+///             .await?;
+///         ActionState::remove(path).await?;
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActionState {
+    id: String,
+    #[serde(rename = "feedbackUrl")]
+    feedback_url: String,
+    phase: Phase,
+}
+
+impl ActionState {
+    pub(crate) fn new(id: &str, feedback_url: &str, phase: Phase) -> Self {
+        Self {
+            id: id.to_string(),
+            feedback_url: feedback_url.to_string(),
+            phase,
+        }
+    }
+
+    /// The id of the action.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The URL feedback about the action is sent to.
+    pub fn feedback_url(&self) -> &str {
+        &self.feedback_url
+    }
+
+    /// The phase the action was in.
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Return the same state with its phase changed to `phase`.
+    pub fn with_phase(self, phase: Phase) -> Self {
+        let mut state = self;
+        state.phase = phase;
+        state
+    }
+
+    /// Save the state to `path`, replacing any previously saved state.
+    pub async fn save(&self, path: &Path) -> Result<(), Error> {
+        let data = serde_json::to_vec(self).map_err(Error::InvalidState)?;
+        write_file_atomic(path, &data).await
+    }
+
+    /// Load the state previously saved to `path`, or `None` if there is none.
+    pub async fn load(path: &Path) -> Result<Option<Self>, Error> {
+        let data = match tokio::fs::read(path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let state = serde_json::from_slice(&data).map_err(Error::InvalidState)?;
+        Ok(Some(state))
+    }
+
+    /// Remove the state saved to `path`, if any.
+    pub async fn remove(path: &Path) -> Result<(), Error> {
+        match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// An update action restored from its [`ActionState`], see [`Client::resume`](crate::ddi::Client::resume).
+#[derive(Debug)]
+pub struct ResumedAction {
+    client: HttpClient,
+    state: ActionState,
+}
+
+impl ResumedAction {
+    pub(crate) fn new(client: HttpClient, state: ActionState) -> Self {
+        Self { client, state }
+    }
+
+    /// The state the action has been restored from.
+    pub fn state(&self) -> &ActionState {
+        &self.state
+    }
+
+    /// Send feedback to server about this action.
+    ///
+    /// # Arguments
+    /// * `execution`: status of the action execution.
+    /// * `finished`: defined status of the result. The action will be kept open on the server until the controller on the device reports either [`Finished::Success`] or [`Finished::Failure`].
+    /// * `details`: list of details message information.
+    pub async fn send_feedback(
+        &self,
+        execution: Execution,
+        finished: Finished,
+        details: Vec<&str>,
+    ) -> Result<(), Error> {
+        send_feedback_to::<bool>(
+            &self.client,
+            &self.state.feedback_url,
+            &self.state.id,
+            execution,
+            finished,
+            None,
+            details,
+        )
+        .await
+    }
+
+    /// Build feedback about this action, with typed progress and optional status
+    /// code and timestamp, then send it using [`FeedbackBuilder::send`].
+    pub fn feedback(&self, execution: Execution, finished: Finished) -> FeedbackBuilder<'_> {
+        let url = self.state.feedback_url.parse().map_err(Error::from);
+        FeedbackBuilder::new(&self.client, url, &self.state.id, execution, finished)
+    }
+}
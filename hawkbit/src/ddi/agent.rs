@@ -0,0 +1,383 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// High-level agent driving the DDI lifecycle
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::ddi::action_state::{ActionState, Phase};
+use crate::ddi::client::{Client, Error};
+use crate::ddi::common::{Execution, Finished};
+use crate::ddi::deployment_base::{DownloadedArtifact, MaintenanceWindow, Type, Update};
+use crate::ddi::download::{DownloadOptions, VerificationPolicy};
+use crate::ddi::poll::Reply;
+
+// Used until the server provided its own polling sleep
+const DEFAULT_POLLING_SLEEP: Duration = Duration::from_secs(60);
+
+/// Result of an [`UpdateHandler`] callback, reported to the server as feedback.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The action succeeded, with optional details messages.
+    Success(Vec<String>),
+    /// The action failed, with optional details messages.
+    Failure(Vec<String>),
+}
+
+/// Device specific logic called by an [`Agent`] to handle the requests from the server.
+#[async_trait]
+pub trait UpdateHandler: Send + Sync {
+    /// The configuration data of the device.
+    type Config: Serialize + Send;
+
+    /// Called when the server requests the device configuration,
+    /// the returned data is uploaded to the server.
+    async fn on_config_request(&self) -> Self::Config;
+
+    /// Called once all the `artifacts` of `update` have been downloaded and verified, to install them.
+    ///
+    /// Not called if the server does not allow the update to be installed yet,
+    /// see [`Update::update_type`] and [`Update::maintenance_window`].
+    /// The artifacts are then downloaded and the action is kept open until
+    /// a later polling allows the installation, without downloading them again.
+    async fn on_update(&self, update: &Update, artifacts: Vec<DownloadedArtifact>) -> Outcome;
+
+    /// Called when the server requests to cancel the action `action_id`.
+    ///
+    /// Returning [`Outcome::Failure`] rejects the cancellation.
+    async fn on_cancel(&self, action_id: &str) -> Outcome;
+
+    /// Called when the agent starts with the `state` of an update interrupted
+    /// while being installed, typically by a reboot, to check if it succeeded.
+    /// Only used if a state file has been set using [`Agent::state_file`].
+    ///
+    /// Default to reporting the update as failed.
+    async fn on_resume(&self, _state: &ActionState) -> Outcome {
+        Outcome::Failure(vec!["Update interrupted".to_string()])
+    }
+
+    /// Called when the agent failed to process a request from the server.
+    /// The agent keeps running and will try again at the next polling.
+    async fn on_error(&self, _error: &Error) {}
+}
+
+/// High-level agent regularly polling the server and handling its requests
+/// using an [`UpdateHandler`].
+///
+/// The agent downloads and verifies the updates before passing them to the handler
+/// and reports the outcome of each request to the server.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use async_trait::async_trait;
+/// use hawkbit::ddi::{Agent, Client, DownloadedArtifact, Outcome, Update, UpdateHandler};
+/// use tokio_util::sync::CancellationToken;
+///
+/// struct Handler;
+///
+/// #[async_trait]
+/// impl UpdateHandler for Handler {
+///     type Config = serde_json::Value;
+///
+///     async fn on_config_request(&self) -> Self::Config {
+///         serde_json::json!({"HwRevision": "1.0"})
+///     }
+///
+///     async fn on_update(&self, _update: &Update, _artifacts: Vec<DownloadedArtifact>) -> Outcome {
+///         Outcome::Success(vec!["Installed".to_string()])
+///     }
+///
+///     async fn on_cancel(&self, _action_id: &str) -> Outcome {
+///         Outcome::Success(vec![])
+///     }
+/// }
+///
+/// # async fn run() {
+/// let client = Client::new("http://my-server.com:8080", "DEFAULT", "my-device", "my-token").unwrap();
+/// let agent = Agent::new(client, Handler, Path::new("./download/"));
+///
+/// let token = CancellationToken::new();
+/// // call token.cancel() to stop the agent
+/// agent.run(token).await;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Agent<H: UpdateHandler> {
+    client: Client,
+    handler: H,
+    download_dir: PathBuf,
+    download_options: DownloadOptions,
+    state_file: Option<PathBuf>,
+    // id and artifacts of the update waiting for the server to allow its installation
+    deferred: Mutex<Option<(String, Vec<DownloadedArtifact>)>>,
+}
+
+impl<H: UpdateHandler> Agent<H> {
+    /// Create a new agent using `client` to talk to the server, `handler` to process
+    /// its requests and downloading the updates to `download_dir`.
+    pub fn new(client: Client, handler: H, download_dir: &Path) -> Self {
+        Self {
+            client,
+            handler,
+            download_dir: download_dir.to_path_buf(),
+            download_options: DownloadOptions::default()
+                .verification(VerificationPolicy::AllAvailable),
+            state_file: None,
+            deferred: Mutex::new(None),
+        }
+    }
+
+    /// Set the options used to download the updates, default to verifying all the
+    /// available checksums, see [`DownloadOptions::verification`].
+    pub fn download_options(self, options: DownloadOptions) -> Self {
+        let mut agent = self;
+        agent.download_options = options;
+        agent
+    }
+
+    /// Save the state of the update being installed to `path`, so its result
+    /// can be reported using [`UpdateHandler::on_resume`] if the agent is
+    /// restarted before the installation completes.
+    pub fn state_file(self, path: &Path) -> Self {
+        let mut agent = self;
+        agent.state_file = Some(path.to_path_buf());
+        agent
+    }
+
+    /// The handler of the agent.
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Run the agent until `token` is cancelled.
+    ///
+    /// The request being processed, if any, is completed before returning.
+    ///
+    /// If an interrupted update has to be resumed, no other update is processed until
+    /// its result has been reported to the server, which is retried at each polling.
+    pub async fn run(&self, token: CancellationToken) {
+        let mut polling_sleep = DEFAULT_POLLING_SLEEP;
+        // `None` until the interrupted update, if any, has been reported
+        let mut resumed: Option<Option<String>> = None;
+
+        while !token.is_cancelled() {
+            if resumed.is_none() {
+                match self.resume().await {
+                    Ok(action_id) => resumed = Some(action_id),
+                    Err(e) => self.handler.on_error(&e).await,
+                }
+                // the handler may have stopped the agent while resuming
+                if token.is_cancelled() {
+                    break;
+                }
+            }
+
+            match self.client.poll().await {
+                Ok(reply) => {
+                    self.process(&reply, resumed.as_ref()).await;
+
+                    match reply.polling_sleep() {
+                        Ok(sleep) => polling_sleep = sleep,
+                        Err(e) => self.handler.on_error(&e).await,
+                    }
+                }
+                Err(e) => self.handler.on_error(&e).await,
+            }
+
+            tokio::select! {
+                _ = token.cancelled() => {},
+                _ = tokio::time::sleep(polling_sleep) => {},
+            }
+        }
+    }
+
+    /// Report the result of the interrupted update, if any, returning its action id.
+    async fn resume(&self) -> Result<Option<String>, Error> {
+        let path = match &self.state_file {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let state = match ActionState::load(path).await? {
+            Some(state) => state,
+            None => return Ok(None),
+        };
+        let action_id = state.id().to_string();
+
+        let outcome = self.handler.on_resume(&state).await;
+        let (finished, details) = outcome.feedback();
+        self.client
+            .resume(state)
+            .send_feedback(Execution::Closed, finished, details)
+            .await?;
+
+        ActionState::remove(path).await?;
+        Ok(Some(action_id))
+    }
+
+    /// Process the requests of `reply`, updates are only processed once the
+    /// interrupted update, if any, has been `resumed`.
+    async fn process(&self, reply: &Reply, resumed: Option<&Option<String>>) {
+        if let Err(e) = self.process_config_request(reply).await {
+            self.handler.on_error(&e).await;
+        }
+        if let Some(resumed_id) = resumed {
+            if let Err(e) = self.process_update(reply, resumed_id.as_deref()).await {
+                self.handler.on_error(&e).await;
+            }
+        }
+        if let Err(e) = self.process_cancel_action(reply).await {
+            self.handler.on_error(&e).await;
+        }
+    }
+
+    async fn process_config_request(&self, reply: &Reply) -> Result<(), Error> {
+        if let Some(request) = reply.config_data_request() {
+            let data = self.handler.on_config_request().await;
+
+            request
+                .upload(Execution::Closed, Finished::Success, None, data, vec![])
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn process_update(&self, reply: &Reply, resumed_id: Option<&str>) -> Result<(), Error> {
+        let update = match reply.update() {
+            Some(update) => update.fetch().await?,
+            None => return Ok(()),
+        };
+
+        // the server may not have processed the feedback of the resumed update yet,
+        // such as if it has been queued in the feedback journal
+        if resumed_id == Some(update.id()) {
+            return Ok(());
+        }
+
+        let mut deferred = self.deferred.lock().await;
+        let artifacts = match deferred.take() {
+            // downloaded by a previous polling, only check if it can be installed now
+            Some((id, artifacts)) if id == update.id() => {
+                if !installation_allowed(&update) {
+                    *deferred = Some((id, artifacts));
+                    return Ok(());
+                }
+                artifacts
+            }
+            _ => {
+                let artifacts = match self.download_update(&update).await? {
+                    Some(artifacts) => artifacts,
+                    None => return Ok(()),
+                };
+
+                // the action is kept open until the server allows the installation,
+                // the update is then processed again at a later polling
+                if !installation_allowed(&update) {
+                    update
+                        .send_feedback(
+                            Execution::Proceeding,
+                            Finished::None,
+                            vec!["Waiting for installation"],
+                        )
+                        .await?;
+                    *deferred = Some((update.id().to_string(), artifacts));
+                    return Ok(());
+                }
+                artifacts
+            }
+        };
+        drop(deferred);
+
+        if let Some(path) = &self.state_file {
+            update.action_state(Phase::Installing)?.save(path).await?;
+        }
+
+        let outcome = self.handler.on_update(&update, artifacts).await;
+        let (finished, details) = outcome.feedback();
+        update
+            .send_feedback(Execution::Closed, finished, details)
+            .await?;
+
+        match &self.state_file {
+            Some(path) => ActionState::remove(path).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Download the artifacts of `update`, if allowed by the server.
+    async fn download_update(
+        &self,
+        update: &Update,
+    ) -> Result<Option<Vec<DownloadedArtifact>>, Error> {
+        if update.download_type() == Type::Skip {
+            return Ok(None);
+        }
+
+        update
+            .send_feedback(Execution::Proceeding, Finished::None, vec!["Downloading"])
+            .await?;
+
+        // artifacts are verified while being downloaded
+        match update
+            .download_with_options(&self.download_dir, &self.download_options)
+            .await
+        {
+            Ok(artifacts) => Ok(Some(artifacts)),
+            Err(e) => {
+                let details = format!("Failed to download update: {}", e);
+                update
+                    .send_feedback(Execution::Closed, Finished::Failure, vec![&details])
+                    .await?;
+                Err(e)
+            }
+        }
+    }
+
+    async fn process_cancel_action(&self, reply: &Reply) -> Result<(), Error> {
+        let cancel_action = match reply.cancel_action() {
+            Some(cancel_action) => cancel_action,
+            None => return Ok(()),
+        };
+
+        let id = cancel_action.id().await?;
+        let outcome = self.handler.on_cancel(&id).await;
+        let execution = match outcome {
+            Outcome::Success(_) => Execution::Closed,
+            Outcome::Failure(_) => Execution::Rejected,
+        };
+        let (finished, details) = outcome.feedback();
+
+        cancel_action
+            .send_feedback(execution, finished, details)
+            .await
+    }
+}
+
+impl Outcome {
+    fn feedback(&self) -> (Finished, Vec<&str>) {
+        match self {
+            Outcome::Success(details) => (
+                Finished::Success,
+                details.iter().map(|d| d.as_str()).collect(),
+            ),
+            Outcome::Failure(details) => (
+                Finished::Failure,
+                details.iter().map(|d| d.as_str()).collect(),
+            ),
+        }
+    }
+}
+
+/// Return `true` if the server allows `update` to be installed now.
+fn installation_allowed(update: &Update) -> bool {
+    update.update_type() != Type::Skip
+        && update.maintenance_window() != Some(MaintenanceWindow::Unavailable)
+}
@@ -3,11 +3,13 @@
 
 // Cancelled operation
 
-use reqwest::Client;
 use serde::Deserialize;
+use tokio::sync::OnceCell;
 
 use crate::ddi::client::Error;
-use crate::ddi::common::{send_feedback_internal, Execution, Finished};
+use crate::ddi::common::{feedback_url, send_feedback_internal, Execution, Finished};
+use crate::ddi::feedback::FeedbackBuilder;
+use crate::ddi::http::HttpClient;
 
 /// A request from the server to cancel an update.
 ///
@@ -18,22 +20,35 @@ use crate::ddi::common::{send_feedback_internal, Execution, Finished};
 /// [`Finished::Success`] or [`Finished::Failure`].
 #[derive(Debug)]
 pub struct CancelAction {
-    client: Client,
+    client: HttpClient,
     url: String,
+    id: OnceCell<String>,
 }
 
 impl CancelAction {
-    pub(crate) fn new(client: Client, url: String) -> Self {
-        Self { client, url }
+    pub(crate) fn new(client: HttpClient, url: String) -> Self {
+        Self {
+            client,
+            url,
+            id: OnceCell::new(),
+        }
     }
 
     /// Retrieve the id of the action to cancel.
+    ///
+    /// The id is only fetched from the server once.
     pub async fn id(&self) -> Result<String, Error> {
-        let reply = self.client.get(&self.url).send().await?;
-        reply.error_for_status_ref()?;
+        let id = self
+            .id
+            .get_or_try_init(|| async {
+                let reply = self.client.send(self.client.get(&self.url)).await?;
+
+                let reply = reply.json::<CancelReply>().await?;
+                Ok::<_, Error>(reply.cancel_action.stop_id)
+            })
+            .await?;
 
-        let reply = reply.json::<CancelReply>().await?;
-        Ok(reply.cancel_action.stop_id)
+        Ok(id.clone())
     }
 
     /// Send feedback to server about this cancel action.
@@ -42,6 +57,9 @@ impl CancelAction {
     /// * `execution`: status of the action execution.
     /// * `finished`: defined status of the result. The action will be kept open on the server until the controller on the device reports either [`Finished::Success`] or [`Finished::Failure`].
     /// * `details`: list of details message information.
+    ///
+    /// If the client uses a feedback journal, see [`ClientBuilder::feedback_journal`](crate::ddi::ClientBuilder::feedback_journal),
+    /// the feedback is queued if the server cannot be reached.
     pub async fn send_feedback(
         &self,
         execution: Execution,
@@ -61,10 +79,21 @@ impl CancelAction {
         )
         .await
     }
+
+    /// Build feedback about this cancel action, with typed progress and optional
+    /// status code and timestamp, then send it using [`FeedbackBuilder::send`].
+    ///
+    /// The id of the action is fetched from the server when sending if needed,
+    /// see [`CancelAction::id`].
+    pub fn feedback(&self, execution: Execution, finished: Finished) -> FeedbackBuilder<'_> {
+        let url = feedback_url(&self.url);
+        FeedbackBuilder::cancel_action(&self.client, url, self, execution, finished)
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct CancelReply {
+    #[allow(dead_code)]
     id: String,
     #[serde(rename = "cancelAction")]
     cancel_action: CancelActionReply,
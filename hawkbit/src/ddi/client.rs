@@ -1,18 +1,57 @@
 // Copyright 2020, Collabora Ltd.
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::convert::TryInto;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use reqwest::header::HeaderValue;
+use reqwest::{Certificate, Identity, Proxy, StatusCode};
 use thiserror::Error;
 use url::Url;
 
+use crate::ddi::action_state::{ActionState, ResumedAction};
+use crate::ddi::confirmation::{self, AutoConfirm};
+use crate::ddi::download::LinkPolicy;
+use crate::ddi::http::HttpClient;
+use crate::ddi::journal::FeedbackJournal;
 use crate::ddi::poll;
+use crate::ddi::rate_limit::RateLimiter;
+use crate::ddi::retry::RetryPolicy;
+use crate::ddi::software_module::SoftwareModule;
 
 /// [Direct Device Integration](https://www.eclipse.org/hawkbit/apis/ddi_api/) client.
 #[derive(Debug, Clone)]
 pub struct Client {
     base_url: Url,
-    client: reqwest::Client,
+    client: HttpClient,
+}
+
+/// Authentication method used by the controller to identify itself to the server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Auth {
+    /// Secret token of the controller, sent as `Authorization: TargetToken <token>`.
+    TargetToken(String),
+    /// Tenant wide token shared by many controllers, sent as `Authorization: GatewayToken <token>`.
+    GatewayToken(String),
+    /// No `Authorization` header, for servers allowing anonymous access or
+    /// authenticating controllers using client certificates.
+    None,
+}
+
+impl Auth {
+    fn header(&self) -> Result<Option<HeaderValue>, Error> {
+        let value = match self {
+            Auth::TargetToken(token) => format!("TargetToken {}", token),
+            Auth::GatewayToken(token) => format!("GatewayToken {}", token),
+            Auth::None => return Ok(None),
+        };
+
+        let mut header = HeaderValue::try_from(value)?;
+        header.set_sensitive(true);
+        Ok(Some(header))
+    }
 }
 
 /// DDI errors
@@ -34,10 +73,154 @@ pub enum Error {
     /// IO error
     #[error("Failed to download update")]
     Io(#[from] std::io::Error),
+    /// Error serializing or parsing a saved action state
+    #[error("Invalid action state")]
+    InvalidState(#[source] serde_json::Error),
+    /// The server provided a file name which cannot be safely used
+    #[error("Invalid file name: {0:?}")]
+    InvalidFileName(String),
+    /// The size of a downloaded artifact does not match the one announced by the server
+    #[error("Invalid size: expected {expected} bytes, received {received}")]
+    SizeMismatch {
+        /// The size announced by the server
+        expected: u64,
+        /// The number of bytes received, which may stop early when exceeding the expected size
+        received: u64,
+    },
+    /// The server did not provide any link to download an artifact allowed by
+    /// the [`LinkPolicy`] of the client
+    #[error("No download link for {0:?}")]
+    MissingLink(String),
+    /// The md5sum file of an artifact provided by the server is invalid
+    #[error("Invalid md5sum file: {0:?}")]
+    InvalidMd5sum(String),
+    /// The signature of an artifact, or a trusted key, is invalid
+    #[cfg(feature = "signature")]
+    #[error("Invalid signature")]
+    InvalidSignature(#[source] minisign_verify::Error),
+    /// The server did not provide the signature of an artifact
+    #[cfg(feature = "signature")]
+    #[error("Missing signature of {0:?}")]
+    MissingSignature(String),
+    /// The signature of an artifact is larger than expected
+    #[cfg(feature = "signature")]
+    #[error("Signature {0:?} is too large")]
+    SignatureTooLarge(String),
+    /// Error serializing or parsing the feedback journal
+    #[error("Invalid feedback journal")]
+    InvalidJournal(#[source] serde_json::Error),
     /// Invalid checksum
     #[cfg(feature = "hash-digest")]
     #[error("Invalid Checksum")]
     ChecksumError(crate::ddi::deployment_base::ChecksumType),
+    /// The server rejected the credentials of the controller (401 or 403)
+    #[error("Unauthorized: {0}")]
+    Unauthorized(ServerError),
+    /// The controller or the requested resource, such as an action, is unknown to the server (404)
+    #[error("Not found: {0}")]
+    NotFound(ServerError),
+    /// The action has already been closed or is no longer valid (409 or 410)
+    #[error("Action closed: {0}")]
+    ActionClosed(ServerError),
+    /// The server is rate limiting the controller (429)
+    #[error("Too many requests: {0}")]
+    TooManyRequests(ServerError),
+    /// The server failed to process the request (5xx)
+    #[error("Server failure: {0}")]
+    ServerFailure(ServerError),
+    /// The server replied with any other error status
+    #[error("Request failed: {0}")]
+    HttpStatus(ServerError),
+}
+
+impl Error {
+    pub(crate) fn from_server_error(error: ServerError) -> Self {
+        match error.status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Error::Unauthorized(error),
+            StatusCode::NOT_FOUND => Error::NotFound(error),
+            StatusCode::CONFLICT | StatusCode::GONE => Error::ActionClosed(error),
+            StatusCode::TOO_MANY_REQUESTS => Error::TooManyRequests(error),
+            s if s.is_server_error() => Error::ServerFailure(error),
+            _ => Error::HttpStatus(error),
+        }
+    }
+
+    /// The HTTP status code returned by the server, if the error is caused by an error reply.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::ReqwestError(e) => e.status(),
+            Error::Unauthorized(e)
+            | Error::NotFound(e)
+            | Error::ActionClosed(e)
+            | Error::TooManyRequests(e)
+            | Error::ServerFailure(e)
+            | Error::HttpStatus(e) => Some(e.status()),
+            _ => None,
+        }
+    }
+
+    /// The delay the server asked the controller to wait before retrying, if any.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::TooManyRequests(e) | Error::ServerFailure(e) => e.retry_after(),
+            _ => None,
+        }
+    }
+}
+
+/// Error reply from the server.
+#[derive(Debug, Clone)]
+pub struct ServerError {
+    status: StatusCode,
+    error_code: Option<String>,
+    message: Option<String>,
+    retry_after: Option<Duration>,
+}
+
+impl ServerError {
+    pub(crate) fn new(
+        status: StatusCode,
+        error_code: Option<String>,
+        message: Option<String>,
+        retry_after: Option<Duration>,
+    ) -> Self {
+        Self {
+            status,
+            error_code,
+            message,
+            retry_after,
+        }
+    }
+
+    /// The HTTP status code of the reply.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The hawkBit error code, such as `hawkbit.server.error.repo.entitiyNotFound`, if provided by the server.
+    pub fn error_code(&self) -> Option<&str> {
+        self.error_code.as_deref()
+    }
+
+    /// The human readable error message, if provided by the server.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// The delay requested by the server using the `Retry-After` header, if any.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.status)?;
+        if let Some(message) = &self.message {
+            write!(f, " ({})", message)?;
+        }
+        Ok(())
+    }
 }
 
 impl Client {
@@ -48,34 +231,391 @@ impl Client {
     /// * `tenant`: the server tenant
     /// * `controller_id`: the id of the controller
     /// * `key_token`: the secret authentification token of the controller
+    ///
+    /// Use [`ClientBuilder`] to customize the HTTP settings of the client.
     pub fn new(
         url: &str,
         tenant: &str,
         controller_id: &str,
         key_token: &str,
     ) -> Result<Self, Error> {
-        let host: Url = url.parse()?;
-        let path = format!("{}/controller/v1/{}", tenant, controller_id);
-        let base_url = host.join(&path)?;
-
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            reqwest::header::AUTHORIZATION,
-            format!("TargetToken {}", key_token).try_into()?,
-        );
-
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()?;
-        Ok(Self { base_url, client })
+        ClientBuilder::new(url, tenant, controller_id, key_token).build()
     }
 
     /// Poll the server for updates
+    ///
+    /// If a feedback journal is used, the queued feedback are sent once the server replied.
+    /// Failing to send them is logged and does not fail the poll.
     pub async fn poll(&self) -> Result<poll::Reply, Error> {
-        let reply = self.client.get(self.base_url.clone()).send().await?;
-        reply.error_for_status_ref()?;
+        let reply = self
+            .client
+            .send(self.client.get(self.base_url.clone()))
+            .await?;
 
         let reply = reply.json::<poll::ReplyInternal>().await?;
+        // the reply is still useful if the queued feedback cannot be sent
+        if let Err(e) = self.replay_feedback().await {
+            log::warn!("Failed to send the queued feedback: {}", e);
+        }
+
         Ok(poll::Reply::new(reply, self.client.clone()))
     }
+
+    /// Send the feedback queued in the journal, see [`ClientBuilder::feedback_journal`].
+    ///
+    /// Feedback which still cannot be sent are kept in the journal.
+    pub async fn replay_feedback(&self) -> Result<(), Error> {
+        match self.client.journal() {
+            Some(journal) => journal.replay(&self.client).await,
+            None => Ok(()),
+        }
+    }
+
+    /// The number of feedback queued in the journal, see [`ClientBuilder::feedback_journal`].
+    pub async fn pending_feedback(&self) -> usize {
+        match self.client.journal() {
+            Some(journal) => journal.len().await,
+            None => 0,
+        }
+    }
+
+    /// Limit the bandwidth used to download artifacts to `bytes_per_second`, or remove
+    /// the limit if `None`, see [`ClientBuilder::download_rate_limit`].
+    ///
+    /// The new limit also applies to the downloads in progress.
+    pub fn set_download_rate_limit(&self, bytes_per_second: Option<u64>) {
+        self.client.rate_limiter().set_rate(bytes_per_second);
+    }
+
+    /// The current download bandwidth limit, in bytes per second.
+    pub fn download_rate_limit(&self) -> Option<u64> {
+        self.client.rate_limiter().rate()
+    }
+
+    /// Retrieve the artifacts of the software module `module_id`, such as the
+    /// [`Chunk::id`](crate::ddi::Chunk::id) of a previous update, so they can be
+    /// downloaded without any pending action.
+    pub async fn software_module_artifacts(&self, module_id: u64) -> Result<SoftwareModule, Error> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| url::ParseError::SetHostOnCannotBeABaseUrl)?
+            .push("softwaremodules")
+            .push(&module_id.to_string())
+            .push("artifacts");
+
+        let reply = self.client.send(self.client.get(url)).await?;
+        let artifacts = reply.json().await?;
+
+        Ok(SoftwareModule::new(
+            self.client.clone(),
+            module_id,
+            artifacts,
+        ))
+    }
+
+    /// Retrieve the state of the auto-confirmation of the actions.
+    pub async fn auto_confirm(&self) -> Result<AutoConfirm, Error> {
+        confirmation::auto_confirm(&self.client, self.confirmation_base_url()?).await
+    }
+
+    /// Let the server confirm the actions on behalf of the device, including the
+    /// ones created later, until [`Client::deactivate_auto_confirm`] is called.
+    ///
+    /// # Arguments
+    /// * `initiator`: who enabled the auto-confirmation, such as the user of the device.
+    /// * `remark`: an optional comment about the auto-confirmation.
+    pub async fn activate_auto_confirm(
+        &self,
+        initiator: Option<&str>,
+        remark: Option<&str>,
+    ) -> Result<(), Error> {
+        let url = self.confirmation_base_url()?;
+        confirmation::activate_auto_confirm(&self.client, url, initiator, remark).await
+    }
+
+    /// Require the device to confirm the actions again, see
+    /// [`Reply::confirmation_request`](crate::ddi::Reply::confirmation_request).
+    pub async fn deactivate_auto_confirm(&self) -> Result<(), Error> {
+        confirmation::deactivate_auto_confirm(&self.client, self.confirmation_base_url()?).await
+    }
+
+    /// Return the URL of the confirmation API.
+    fn confirmation_base_url(&self) -> Result<Url, Error> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| url::ParseError::SetHostOnCannotBeABaseUrl)?
+            .push("confirmationBase");
+
+        Ok(url)
+    }
+
+    /// Restore an action from its `state`, previously saved using [`ActionState::save`],
+    /// so its result can be reported to the server.
+    pub fn resume(&self, state: ActionState) -> ResumedAction {
+        ResumedAction::new(self.client.clone(), state)
+    }
+}
+
+/// Builder of [`Client`].
+///
+/// The controller can authenticate using one of the [`Auth`] methods, a client certificate
+/// (see [`ClientBuilder::identity`]), or both.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use hawkbit::ddi::ClientBuilder;
+///
+/// let client = ClientBuilder::new("http://my-server.com:8080", "DEFAULT", "my-device", "my-token")
+///     .connect_timeout(Duration::from_secs(10))
+///     .user_agent("my-device/1.0")
+///     .build()
+///     .expect("DDI creation failed");
+/// ```
+#[derive(Debug)]
+pub struct ClientBuilder {
+    url: String,
+    tenant: String,
+    controller_id: String,
+    auth: Auth,
+    identity: Option<Identity>,
+    client: Option<reqwest::Client>,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    proxies: Vec<Proxy>,
+    user_agent: Option<String>,
+    root_certificates: Vec<Certificate>,
+    retry_policy: RetryPolicy,
+    feedback_journal: Option<PathBuf>,
+    download_rate_limit: Option<u64>,
+    link_policy: LinkPolicy,
+}
+
+impl ClientBuilder {
+    /// Start building a new [`Client`].
+    ///
+    /// See [`Client::new`] for the meaning of the arguments.
+    pub fn new(url: &str, tenant: &str, controller_id: &str, key_token: &str) -> Self {
+        Self::new_internal(
+            url,
+            tenant,
+            controller_id,
+            Auth::TargetToken(key_token.to_string()),
+            None,
+        )
+    }
+
+    fn new_internal(
+        url: &str,
+        tenant: &str,
+        controller_id: &str,
+        auth: Auth,
+        identity: Option<Identity>,
+    ) -> Self {
+        Self {
+            url: url.to_string(),
+            tenant: tenant.to_string(),
+            controller_id: controller_id.to_string(),
+            auth,
+            identity,
+            client: None,
+            connect_timeout: None,
+            timeout: None,
+            proxies: Vec::new(),
+            user_agent: None,
+            root_certificates: Vec::new(),
+            retry_policy: RetryPolicy::default(),
+            feedback_journal: None,
+            download_rate_limit: None,
+            link_policy: LinkPolicy::default(),
+        }
+    }
+
+    /// Start building a new [`Client`] authenticating using only a client certificate.
+    ///
+    /// This is meant to be used with servers, or reverse proxies in front of them,
+    /// relying on mutual TLS to authenticate the controllers.
+    ///
+    /// # Arguments
+    /// * `url`: the URL of the hawkBit server, such as `https://my-server.com:8443`
+    /// * `tenant`: the server tenant
+    /// * `controller_id`: the id of the controller
+    /// * `identity`: the client certificate and its private key, see [`reqwest::Identity`]
+    ///   to load it from PEM or PKCS#12 files.
+    pub fn with_identity(url: &str, tenant: &str, controller_id: &str, identity: Identity) -> Self {
+        Self::new_internal(url, tenant, controller_id, Auth::None, Some(identity))
+    }
+
+    /// Set the authentication method of the controller, replacing the one
+    /// defined when creating the builder.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hawkbit::ddi::{Auth, ClientBuilder};
+    ///
+    /// let client = ClientBuilder::new("http://my-server.com:8080", "DEFAULT", "my-device", "")
+    ///     .auth(Auth::GatewayToken("my-gateway-token".to_string()))
+    ///     .build()
+    ///     .expect("DDI creation failed");
+    /// ```
+    pub fn auth(self, auth: Auth) -> Self {
+        let mut builder = self;
+        builder.auth = auth;
+        builder
+    }
+
+    /// Present `identity` as client certificate when connecting to the server,
+    /// in addition to the [`Auth`] method of the controller.
+    pub fn identity(self, identity: Identity) -> Self {
+        let mut builder = self;
+        builder.identity = Some(identity);
+        builder
+    }
+
+    /// Use `client` to send the HTTP requests instead of creating a new one.
+    ///
+    /// All the other HTTP settings of the builder, such as timeouts, proxies
+    /// or client certificate, are ignored as they have to be configured on `client` directly.
+    pub fn client(self, client: reqwest::Client) -> Self {
+        let mut builder = self;
+        builder.client = Some(client);
+        builder
+    }
+
+    /// Set a timeout for only the connect phase of the requests.
+    pub fn connect_timeout(self, timeout: Duration) -> Self {
+        let mut builder = self;
+        builder.connect_timeout = Some(timeout);
+        builder
+    }
+
+    /// Set a timeout for the whole requests, from when they start connecting
+    /// until their response body has been fully received.
+    ///
+    /// Note that this also applies to artifact downloads so it should be
+    /// large enough to download the biggest expected artifact.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        let mut builder = self;
+        builder.timeout = Some(timeout);
+        builder
+    }
+
+    /// Add a proxy used to reach the server.
+    pub fn proxy(self, proxy: Proxy) -> Self {
+        let mut builder = self;
+        builder.proxies.push(proxy);
+        builder
+    }
+
+    /// Set the `User-Agent` header sent with each request.
+    pub fn user_agent(self, user_agent: &str) -> Self {
+        let mut builder = self;
+        builder.user_agent = Some(user_agent.to_string());
+        builder
+    }
+
+    /// Add a custom root certificate to trust when connecting to the server,
+    /// in addition to the system ones.
+    pub fn add_root_certificate(self, certificate: Certificate) -> Self {
+        let mut builder = self;
+        builder.root_certificates.push(certificate);
+        builder
+    }
+
+    /// Set the policy used to retry failed requests to the server.
+    ///
+    /// The policy applies to all the requests, including polling, feedback
+    /// and starting artifact downloads, but interrupted downloads are not resumed.
+    pub fn retry_policy(self, policy: RetryPolicy) -> Self {
+        let mut builder = self;
+        builder.retry_policy = policy;
+        builder
+    }
+
+    /// Store the feedback which could not be sent because the server is unreachable
+    /// in a journal saved to `path`, instead of failing.
+    ///
+    /// The queued feedback are sent in order, before any new one, once the server
+    /// is reachable again, or when polling succeeds. Feedback about the same action
+    /// are collapsed: duplicates are ignored and only the final result of closed
+    /// actions is kept.
+    pub fn feedback_journal(self, path: &Path) -> Self {
+        let mut builder = self;
+        builder.feedback_journal = Some(path.to_path_buf());
+        builder
+    }
+
+    /// Limit the bandwidth used to download artifacts to `bytes_per_second`.
+    ///
+    /// The limit is shared by all the downloads of the client, including
+    /// [`Artifact::download_stream`](crate::ddi::Artifact::download_stream), and can be
+    /// changed later using [`Client::set_download_rate_limit`]. Not limited by default.
+    pub fn download_rate_limit(self, bytes_per_second: u64) -> Self {
+        let mut builder = self;
+        builder.download_rate_limit = Some(bytes_per_second);
+        builder
+    }
+
+    /// Set which links provided by the server are used to download the artifacts,
+    /// default to [`LinkPolicy::PreferHttps`].
+    pub fn link_policy(self, policy: LinkPolicy) -> Self {
+        let mut builder = self;
+        builder.link_policy = policy;
+        builder
+    }
+
+    /// Create the [`Client`].
+    pub fn build(self) -> Result<Client, Error> {
+        let host: Url = self.url.parse()?;
+        let path = format!("{}/controller/v1/{}", self.tenant, self.controller_id);
+        let base_url = host.join(&path)?;
+
+        let authorization = self.auth.header()?;
+        let journal = match &self.feedback_journal {
+            Some(path) => Some(FeedbackJournal::open(path)?),
+            None => None,
+        };
+
+        let client = match self.client {
+            Some(client) => client,
+            None => {
+                let mut builder = reqwest::Client::builder();
+
+                if let Some(timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                if let Some(timeout) = self.timeout {
+                    builder = builder.timeout(timeout);
+                }
+                for proxy in self.proxies {
+                    builder = builder.proxy(proxy);
+                }
+                if let Some(user_agent) = self.user_agent {
+                    builder = builder.user_agent(user_agent);
+                }
+                for certificate in self.root_certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+                if let Some(identity) = self.identity {
+                    builder = builder.identity(identity);
+                }
+
+                builder.build()?
+            }
+        };
+
+        Ok(Client {
+            base_url,
+            client: HttpClient::new(
+                client,
+                authorization,
+                self.retry_policy,
+                journal,
+                RateLimiter::new(self.download_rate_limit),
+                self.link_policy,
+            ),
+        })
+    }
 }
@@ -2,19 +2,28 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use std::fmt;
+use std::path::{Path, PathBuf};
 
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
 use url::Url;
 
 use crate::ddi::client::Error;
 use crate::ddi::feedback::Feedback;
+use crate::ddi::http::HttpClient;
+use crate::ddi::journal::Entry;
 
 #[derive(Debug, Deserialize)]
 pub struct Link {
     href: String,
 }
 
+impl Link {
+    pub(crate) fn href(&self) -> &str {
+        &self.href
+    }
+}
+
 impl fmt::Display for Link {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.href)
@@ -40,6 +49,16 @@ pub enum Execution {
     Resumed,
 }
 
+impl Execution {
+    /// Return `true` if the action is closed once the server received this execution status.
+    pub(crate) fn closes_action(&self) -> bool {
+        matches!(
+            self,
+            Execution::Closed | Execution::Canceled | Execution::Rejected
+        )
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "lowercase")]
 /// Status of a pending operation
@@ -53,7 +72,7 @@ pub enum Finished {
 }
 
 pub(crate) async fn send_feedback_internal<T: Serialize>(
-    client: &Client,
+    client: &HttpClient,
     url: &str,
     id: &str,
     execution: Execution,
@@ -61,6 +80,22 @@ pub(crate) async fn send_feedback_internal<T: Serialize>(
     progress: Option<T>,
     details: Vec<&str>,
 ) -> Result<(), Error> {
+    let url = feedback_url(url)?;
+
+    send_feedback_to(
+        client,
+        url.as_str(),
+        id,
+        execution,
+        finished,
+        progress,
+        details,
+    )
+    .await
+}
+
+/// Return the URL feedback about the action at `url` should be sent to.
+pub(crate) fn feedback_url(url: &str) -> Result<Url, Error> {
     let mut url: Url = url.parse()?;
     {
         let mut paths = url
@@ -70,11 +105,69 @@ pub(crate) async fn send_feedback_internal<T: Serialize>(
     }
     url.set_query(None);
 
+    Ok(url)
+}
+
+pub(crate) async fn send_feedback_to<T: Serialize>(
+    client: &HttpClient,
+    url: &str,
+    id: &str,
+    execution: Execution,
+    finished: Finished,
+    progress: Option<T>,
+    details: Vec<&str>,
+) -> Result<(), Error> {
     let details = details.iter().map(|m| m.to_string()).collect();
     let feedback = Feedback::new(id, execution, finished, progress, details);
 
-    let reply = client.post(&url.to_string()).json(&feedback).send().await?;
-    reply.error_for_status()?;
+    post_feedback(client, url, &feedback).await
+}
+
+/// Send `feedback` to `url`, through the feedback journal if any.
+pub(crate) async fn post_feedback<T: Serialize>(
+    client: &HttpClient,
+    url: &str,
+    feedback: &Feedback<T>,
+) -> Result<(), Error> {
+    let closing = feedback.closes_action();
+
+    match client.journal() {
+        Some(journal) => {
+            let feedback = serde_json::to_value(feedback).map_err(Error::InvalidJournal)?;
+            journal
+                .send(client, Entry::new(url, closing, feedback))
+                .await
+        }
+        None => {
+            client.send(client.post(url).json(feedback)).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Write `data` to `path` using a temporary file synced to the disk, so a crash or
+/// a power loss cannot leave a missing or truncated file behind.
+pub(crate) async fn write_file_atomic(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let mut file = tokio::fs::File::create(&tmp).await?;
+    file.write_all(data).await?;
+    file.sync_all().await?;
+    drop(file);
+
+    tokio::fs::rename(&tmp, path).await?;
+
+    // persist the rename itself
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        tokio::fs::File::open(dir).await?.sync_all().await?;
+    }
 
     Ok(())
 }
@@ -3,20 +3,20 @@
 
 // Structures used to send config data
 
-use reqwest::Client;
 use serde::Serialize;
 
+use crate::ddi::http::HttpClient;
 use crate::ddi::{Error, Execution, Finished};
 
 /// A request from the server asking to upload the device configuration.
 #[derive(Debug)]
 pub struct ConfigRequest {
-    client: Client,
+    client: HttpClient,
     url: String,
 }
 
 impl ConfigRequest {
-    pub(crate) fn new(client: Client, url: String) -> Self {
+    pub(crate) fn new(client: HttpClient, url: String) -> Self {
         Self { client, url }
     }
 
@@ -34,9 +34,10 @@ impl ConfigRequest {
     ) -> Result<(), Error> {
         let details = details.iter().map(|m| m.to_string()).collect();
         let data = ConfigData::new(execution, finished, mode, data, details);
-        let reply = self.client.put(&self.url).json(&data).send().await?;
+        self.client
+            .send(self.client.put(&self.url).json(&data))
+            .await?;
 
-        reply.error_for_status()?;
         Ok(())
     }
 }
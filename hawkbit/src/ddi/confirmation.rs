@@ -0,0 +1,302 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// Confirmation of the actions by the device before they start
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::ddi::client::Error;
+use crate::ddi::common::feedback_url;
+use crate::ddi::deployment_base::{ActionHistory, Chunk, Deployment, MaintenanceWindow, Type};
+use crate::ddi::http::HttpClient;
+
+/// A request from the server asking the device to confirm an action before it starts,
+/// such as to get the consent of its user.
+///
+/// Call [`ConfirmationRequest::fetch()`] to retrieve the details of the action,
+/// then reply using [`ConfirmationRequest::send_feedback`]. Once confirmed, the
+/// action is provided as a regular update, see [`Reply::update`](crate::ddi::Reply::update).
+#[derive(Debug)]
+pub struct ConfirmationRequest {
+    client: HttpClient,
+    url: String,
+}
+
+impl ConfirmationRequest {
+    pub(crate) fn new(client: HttpClient, url: String) -> Self {
+        Self { client, url }
+    }
+
+    /// Retrieve details about the action waiting for confirmation.
+    pub async fn fetch(&self) -> Result<Confirmation, Error> {
+        let reply = self.client.send(self.client.get(&self.url)).await?;
+
+        let reply = reply.json::<Reply>().await?;
+        Ok(Confirmation {
+            client: self.client.clone(),
+            reply,
+        })
+    }
+
+    /// Confirm or deny the action.
+    ///
+    /// # Arguments
+    /// * `response`: whether the action is confirmed or denied.
+    /// * `code`: an optional code, defined by the device, providing more information about the response.
+    /// * `details`: list of details message information.
+    pub async fn send_feedback(
+        &self,
+        response: ConfirmationResponse,
+        code: Option<i32>,
+        details: Vec<&str>,
+    ) -> Result<(), Error> {
+        let mut builder = self.feedback(response).details(details);
+        if let Some(code) = code {
+            builder = builder.code(code);
+        }
+
+        builder.send().await
+    }
+
+    /// Build the response to this request, then send it using
+    /// [`ConfirmationFeedbackBuilder::send`].
+    pub fn feedback(&self, response: ConfirmationResponse) -> ConfirmationFeedbackBuilder<'_> {
+        ConfirmationFeedbackBuilder {
+            client: &self.client,
+            url: feedback_url(&self.url),
+            feedback: Feedback {
+                confirmation: response,
+                code: None,
+                details: Vec::new(),
+            },
+        }
+    }
+
+    /// Let the server confirm the actions on behalf of the device,
+    /// until [`ConfirmationRequest::deactivate_auto_confirm`] is called.
+    ///
+    /// # Arguments
+    /// * `initiator`: who enabled the auto-confirmation, such as the user of the device.
+    /// * `remark`: an optional comment about the auto-confirmation.
+    ///
+    /// Same as [`Client::activate_auto_confirm`](crate::ddi::Client::activate_auto_confirm).
+    pub async fn activate_auto_confirm(
+        &self,
+        initiator: Option<&str>,
+        remark: Option<&str>,
+    ) -> Result<(), Error> {
+        activate_auto_confirm(&self.client, self.base_url()?, initiator, remark).await
+    }
+
+    /// Require the device to confirm the actions again.
+    ///
+    /// Same as [`Client::deactivate_auto_confirm`](crate::ddi::Client::deactivate_auto_confirm).
+    pub async fn deactivate_auto_confirm(&self) -> Result<(), Error> {
+        deactivate_auto_confirm(&self.client, self.base_url()?).await
+    }
+
+    /// Return the URL of the root of the confirmation API, rather than the action.
+    fn base_url(&self) -> Result<Url, Error> {
+        let mut url: Url = self.url.parse()?;
+        url.path_segments_mut()
+            .map_err(|_| url::ParseError::SetHostOnCannotBeABaseUrl)?
+            .pop();
+        url.set_query(None);
+
+        Ok(url)
+    }
+}
+
+/// Builder for the response to a [`ConfirmationRequest`], see [`ConfirmationRequest::feedback`].
+///
+/// Unlike the feedback about the other actions, the confirmation API does not
+/// support progress nor timestamps.
+#[derive(Debug)]
+pub struct ConfirmationFeedbackBuilder<'a> {
+    client: &'a HttpClient,
+    // the URL the feedback is sent to, errors are reported when sending
+    url: Result<Url, Error>,
+    feedback: Feedback,
+}
+
+impl ConfirmationFeedbackBuilder<'_> {
+    /// Add a numeric code, defined by the device, providing more information about the response.
+    pub fn code(self, code: i32) -> Self {
+        let mut builder = self;
+        builder.feedback.code = Some(code);
+        builder
+    }
+
+    /// Set the list of details message information.
+    pub fn details(self, details: Vec<&str>) -> Self {
+        let mut builder = self;
+        builder.feedback.details = details.iter().map(|m| m.to_string()).collect();
+        builder
+    }
+
+    /// Send the response to the server.
+    pub async fn send(self) -> Result<(), Error> {
+        let url = self.url?;
+
+        self.client
+            .send(self.client.post(url).json(&self.feedback))
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// State of the auto-confirmation of the actions, see
+/// [`Client::auto_confirm`](crate::ddi::Client::auto_confirm).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AutoConfirm {
+    active: bool,
+    initiator: Option<String>,
+    remark: Option<String>,
+    #[serde(rename = "activatedAt")]
+    activated_at: Option<u64>,
+}
+
+impl AutoConfirm {
+    /// `true` if the server confirms the actions on behalf of the device.
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// Who enabled the auto-confirmation, if known.
+    pub fn initiator(&self) -> Option<&str> {
+        self.initiator.as_deref()
+    }
+
+    /// The comment about the auto-confirmation, if any.
+    pub fn remark(&self) -> Option<&str> {
+        self.remark.as_deref()
+    }
+
+    /// When the auto-confirmation has been activated, in milliseconds since the epoch.
+    pub fn activated_at(&self) -> Option<u64> {
+        self.activated_at
+    }
+}
+
+/// Retrieve the state of the auto-confirmation from the confirmation API at `url`.
+pub(crate) async fn auto_confirm(client: &HttpClient, url: Url) -> Result<AutoConfirm, Error> {
+    let reply = client.send(client.get(url)).await?;
+    let reply = reply.json::<StateReply>().await?;
+
+    Ok(reply.auto_confirm)
+}
+
+/// Activate the auto-confirmation using the confirmation API at `url`.
+pub(crate) async fn activate_auto_confirm(
+    client: &HttpClient,
+    url: Url,
+    initiator: Option<&str>,
+    remark: Option<&str>,
+) -> Result<(), Error> {
+    let url = endpoint_url(url, "activateAutoConfirm")?;
+    let activation = AutoConfirmActivation { initiator, remark };
+
+    client.send(client.post(url).json(&activation)).await?;
+
+    Ok(())
+}
+
+/// Deactivate the auto-confirmation using the confirmation API at `url`.
+pub(crate) async fn deactivate_auto_confirm(client: &HttpClient, url: Url) -> Result<(), Error> {
+    let url = endpoint_url(url, "deactivateAutoConfirm")?;
+
+    client.send(client.post(url)).await?;
+
+    Ok(())
+}
+
+/// Return the URL of the auto-confirmation `endpoint` of the confirmation API at `url`.
+fn endpoint_url(url: Url, endpoint: &str) -> Result<Url, Error> {
+    let mut url = url;
+    url.path_segments_mut()
+        .map_err(|_| url::ParseError::SetHostOnCannotBeABaseUrl)?
+        .push(endpoint);
+
+    Ok(url)
+}
+
+/// Details of an action waiting for confirmation, see [`ConfirmationRequest::fetch`].
+#[derive(Debug)]
+pub struct Confirmation {
+    client: HttpClient,
+    reply: Reply,
+}
+
+impl Confirmation {
+    /// The id of the action.
+    pub fn id(&self) -> &str {
+        &self.reply.id
+    }
+
+    /// Handling for the download part of the provisioning process.
+    pub fn download_type(&self) -> Type {
+        self.reply.confirmation.download
+    }
+
+    /// Handling for the update part of the provisioning process.
+    pub fn update_type(&self) -> Type {
+        self.reply.confirmation.update
+    }
+
+    /// If set, the update is part of a maintenance window.
+    pub fn maintenance_window(&self) -> Option<MaintenanceWindow> {
+        self.reply.confirmation.maintenance_window
+    }
+
+    /// An iterator on all the software chunks of the update.
+    pub fn chunks(&self) -> impl Iterator<Item = Chunk<'_>> {
+        self.reply.confirmation.chunks(&self.client)
+    }
+
+    /// The history of the action, if provided by the server.
+    pub fn action_history(&self) -> Option<&ActionHistory> {
+        self.reply.action_history.as_ref()
+    }
+}
+
+/// Response of the device to a [`ConfirmationRequest`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmationResponse {
+    /// The action can be started.
+    Confirmed,
+    /// The action should not be started. It is kept waiting for confirmation on the server.
+    Denied,
+}
+
+#[derive(Debug, Deserialize)]
+struct Reply {
+    id: String,
+    confirmation: Deployment,
+    #[serde(rename = "actionHistory")]
+    action_history: Option<ActionHistory>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StateReply {
+    #[serde(rename = "autoConfirm")]
+    auto_confirm: AutoConfirm,
+}
+
+#[derive(Debug, Serialize)]
+struct Feedback {
+    confirmation: ConfirmationResponse,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<i32>,
+    details: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AutoConfirmActivation<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    initiator: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remark: Option<&'a str>,
+}
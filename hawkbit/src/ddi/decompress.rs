@@ -0,0 +1,185 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// Decompression of the artifacts while they are downloaded
+
+use std::io;
+
+use bytes::Bytes;
+use futures::{Stream, TryStreamExt};
+use tokio::io::AsyncBufReadExt;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::ddi::client::Error;
+
+/// Compression formats artifacts can be decompressed from, depending on the enabled
+/// `decompress-*` features.
+#[derive(Debug, strum::Display, Clone, Copy, PartialEq)]
+pub enum Compression {
+    /// gzip, using the `.gz` extension
+    #[cfg(feature = "decompress-gzip")]
+    Gzip,
+    /// xz, using the `.xz` extension
+    #[cfg(feature = "decompress-xz")]
+    Xz,
+    /// Zstandard, using the `.zst` extension
+    #[cfg(feature = "decompress-zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// All the supported formats, with their file extension and magic bytes.
+    const FORMATS: &'static [(Compression, &'static str, &'static [u8])] = &[
+        #[cfg(feature = "decompress-gzip")]
+        (Compression::Gzip, "gz", &[0x1f, 0x8b]),
+        #[cfg(feature = "decompress-xz")]
+        (Compression::Xz, "xz", &[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+        #[cfg(feature = "decompress-zstd")]
+        (Compression::Zstd, "zst", &[0x28, 0xb5, 0x2f, 0xfd]),
+    ];
+
+    /// The format matching the extension of `file_name`, if any.
+    pub(crate) fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        Self::FORMATS
+            .iter()
+            .find(|(_, ext, _)| *ext == extension)
+            .map(|(compression, _, _)| *compression)
+    }
+
+    /// The format of the compressed `data` starting with its magic bytes, if any.
+    fn from_magic(data: &[u8]) -> Option<Self> {
+        Self::FORMATS
+            .iter()
+            .find(|(_, _, magic)| data.starts_with(magic))
+            .map(|(compression, _, _)| *compression)
+    }
+}
+
+/// How the artifacts are decompressed, see
+/// [`DownloadOptions::decompress`](crate::ddi::DownloadOptions::decompress).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decompression {
+    /// Detect the format from the file name extension of the artifact, or else from
+    /// its first bytes. Artifacts which are not compressed are kept as they are.
+    Detect,
+    /// Decompress using the given format.
+    Codec(Compression),
+}
+
+impl Decompression {
+    /// The format to use to decompress `file_name`, starting with `data`.
+    pub(crate) fn compression(&self, file_name: &str, data: &[u8]) -> Option<Compression> {
+        match self {
+            Decompression::Detect => {
+                Compression::from_file_name(file_name).or_else(|| Compression::from_magic(data))
+            }
+            Decompression::Codec(compression) => Some(*compression),
+        }
+    }
+}
+
+/// The name of `file_name` once decompressed, without its compression extension.
+pub(crate) fn decompressed_file_name(file_name: &str) -> &str {
+    match Compression::from_file_name(file_name) {
+        Some(_) => file_name
+            .rsplit_once('.')
+            .map_or(file_name, |(name, _)| name),
+        None => file_name,
+    }
+}
+
+/// Decompress `stream` of the artifact `file_name` on the fly.
+pub(crate) async fn decompress_stream<S>(
+    stream: S,
+    decompression: Decompression,
+    file_name: &str,
+) -> Result<impl Stream<Item = Result<Bytes, Error>>, Error>
+where
+    S: Stream<Item = Result<Bytes, Error>> + Send + Unpin + 'static,
+{
+    let mut reader = StreamReader::new(stream.map_err(into_io_error));
+    // peek the first bytes to detect the format
+    let data = reader.fill_buf().await.map_err(from_io_error)?;
+
+    // decoders are set to read the whole stream, even past the end of the compressed
+    // data, so the errors raised at its end, such as invalid checksums, are reported
+    let stream: std::pin::Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>> =
+        match decompression.compression(file_name, data) {
+            None => Box::pin(ReaderStream::new(reader)),
+            #[cfg(feature = "decompress-gzip")]
+            Some(Compression::Gzip) => {
+                let mut decoder = async_compression::tokio::bufread::GzipDecoder::new(reader);
+                decoder.multiple_members(true);
+                Box::pin(ReaderStream::new(decoder))
+            }
+            #[cfg(feature = "decompress-xz")]
+            Some(Compression::Xz) => {
+                let mut decoder = async_compression::tokio::bufread::XzDecoder::new(reader);
+                decoder.multiple_members(true);
+                Box::pin(ReaderStream::new(decoder))
+            }
+            #[cfg(feature = "decompress-zstd")]
+            Some(Compression::Zstd) => {
+                let mut decoder = async_compression::tokio::bufread::ZstdDecoder::new(reader);
+                decoder.multiple_members(true);
+                Box::pin(ReaderStream::new(decoder))
+            }
+        };
+
+    Ok(stream.map_err(from_io_error))
+}
+
+// Errors of the downloaded stream, such as invalid checksums, are passed
+// through the decoder as IO errors and restored afterward.
+fn into_io_error(error: Error) -> io::Error {
+    match error {
+        Error::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+fn from_io_error(error: io::Error) -> Error {
+    if matches!(error.get_ref(), Some(e) if e.is::<Error>()) {
+        // the inner error type has just been checked
+        let inner = error.into_inner().unwrap();
+        return *inner.downcast::<Error>().unwrap();
+    }
+
+    Error::Io(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        assert_eq!(Compression::from_file_name("test.txt"), None);
+        assert_eq!(Compression::from_magic(b"hello world"), None);
+        assert_eq!(decompressed_file_name("test.txt"), "test.txt");
+
+        #[cfg(feature = "decompress-gzip")]
+        {
+            assert_eq!(
+                Compression::from_file_name("rootfs.ext4.gz"),
+                Some(Compression::Gzip)
+            );
+            assert_eq!(
+                Compression::from_magic(&[0x1f, 0x8b, 0x08]),
+                Some(Compression::Gzip)
+            );
+            assert_eq!(decompressed_file_name("rootfs.ext4.gz"), "rootfs.ext4");
+        }
+        #[cfg(feature = "decompress-xz")]
+        assert_eq!(
+            Compression::from_file_name("rootfs.ext4.xz"),
+            Some(Compression::Xz)
+        );
+        #[cfg(feature = "decompress-zstd")]
+        assert_eq!(
+            Compression::from_magic(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+            Some(Compression::Zstd)
+        );
+    }
+}
@@ -4,62 +4,109 @@
 // Structures when querying deployment
 
 use std::path::{Path, PathBuf};
+#[cfg(any(feature = "hash-digest", feature = "decompress"))]
+use std::{pin::Pin, task::Poll};
 
 use bytes::Bytes;
-use futures::{prelude::*, TryStreamExt};
-use reqwest::{Client, Response};
+use futures::{prelude::*, stream, TryStreamExt};
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{RequestBuilder, Response, StatusCode};
 use serde::de::{Deserializer, Error as _, IgnoredAny, MapAccess, Visitor};
 use serde::{Deserialize, Serialize};
 
-use tokio::{
-    fs::{DirBuilder, File},
-    io::AsyncWriteExt,
-};
+use tokio::fs::{DirBuilder, File, OpenOptions};
 
+use crate::ddi::action_state::{ActionState, Phase};
 use crate::ddi::client::Error;
-use crate::ddi::common::{send_feedback_internal, Execution, Finished, Link};
+use crate::ddi::common::{feedback_url, send_feedback_internal, Execution, Finished, Link};
+use crate::ddi::download::{
+    check_file_name, send_progress_feedback, ArtifactWriter, DownloadOptions, FileNaming,
+    LinkPolicy, ProgressTracker, VerificationPolicy,
+};
+use crate::ddi::feedback::{Feedback, FeedbackBuilder, Progress};
+use crate::ddi::http::HttpClient;
+
+#[cfg(feature = "decompress")]
+use crate::ddi::decompress::{decompress_stream, Decompression};
+#[cfg(feature = "signature")]
+use crate::ddi::signature::{
+    decode_signature, verify_stream, SignatureCheck, TrustedKeys, VerifiedSignature,
+};
+#[cfg(feature = "signature")]
+use minisign_verify::Signature;
 
 #[derive(Debug)]
 /// A pending update whose details have not been retrieved yet.
 ///
 /// Call [`UpdatePreFetch::fetch()`] to retrieve the details from server.
 pub struct UpdatePreFetch {
-    client: Client,
+    client: HttpClient,
     url: String,
 }
 
 impl UpdatePreFetch {
-    pub(crate) fn new(client: Client, url: String) -> Self {
+    pub(crate) fn new(client: HttpClient, url: String) -> Self {
         Self { client, url }
     }
 
     /// Retrieve details about the update.
     pub async fn fetch(self) -> Result<Update, Error> {
-        let reply = self.client.get(&self.url).send().await?;
-        reply.error_for_status_ref()?;
+        let request = self.client.get(&self.url);
+        self.fetch_internal(request).await
+    }
+
+    /// Retrieve details about the update, including up to `messages` of the messages
+    /// previously sent by the target as feedback about this action,
+    /// see [`Update::action_history`].
+    ///
+    /// This is useful to resume the update where it stopped, such as after a reboot,
+    /// without having to store its progress locally.
+    pub async fn fetch_with_history(self, messages: u32) -> Result<Update, Error> {
+        let request = self
+            .client
+            .get(&self.url)
+            .query(&[("actionHistory", messages)]);
+        self.fetch_internal(request).await
+    }
+
+    async fn fetch_internal(self, request: RequestBuilder) -> Result<Update, Error> {
+        let reply = self.client.send(request).await?;
 
         let reply = reply.json::<Reply>().await?;
         Ok(Update::new(self.client, reply, self.url))
     }
 }
 
+/// Reply describing an action, also used for the installed action.
 #[derive(Debug, Deserialize)]
-struct Reply {
-    id: String,
-    deployment: Deployment,
+pub(crate) struct Reply {
+    pub(crate) id: String,
+    pub(crate) deployment: Deployment,
     #[serde(rename = "actionHistory")]
-    action_history: Option<ActionHistory>,
+    pub(crate) action_history: Option<ActionHistory>,
 }
 
+/// Details of a deployment, also used by the actions waiting for confirmation.
 #[derive(Debug, Deserialize)]
-struct Deployment {
-    download: Type,
-    update: Type,
+pub(crate) struct Deployment {
+    pub(crate) download: Type,
+    pub(crate) update: Type,
     #[serde(rename = "maintenanceWindow")]
-    maintenance_window: Option<MaintenanceWindow>,
+    pub(crate) maintenance_window: Option<MaintenanceWindow>,
     chunks: Vec<ChunkInternal>,
 }
 
+impl Deployment {
+    /// An iterator on all the software chunks of the deployment.
+    pub(crate) fn chunks(&self, client: &HttpClient) -> impl Iterator<Item = Chunk<'_>> {
+        let client = client.clone();
+
+        self.chunks
+            .iter()
+            .map(move |c| Chunk::new(c, client.clone()))
+    }
+}
+
 /// How the download or update should be processed by the target.
 #[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -83,7 +130,9 @@ pub enum MaintenanceWindow {
 }
 
 #[derive(Debug, Deserialize)]
-struct ChunkInternal {
+pub(crate) struct ChunkInternal {
+    #[serde(default)]
+    id: Option<u64>,
     #[serde(default)]
     metadata: Vec<Metadata>,
     part: String,
@@ -92,6 +141,29 @@ struct ChunkInternal {
     artifacts: Vec<ArtifactInternal>,
 }
 
+impl ChunkInternal {
+    /// A chunk holding the `artifacts` of the software module `id`, retrieved
+    /// outside of any deployment.
+    pub(crate) fn software_module(id: u64, artifacts: Vec<ArtifactInternal>) -> Self {
+        Self {
+            id: Some(id),
+            metadata: Vec::new(),
+            part: String::new(),
+            name: String::new(),
+            version: String::new(),
+            artifacts,
+        }
+    }
+
+    pub(crate) fn artifacts(&self, client: &HttpClient) -> impl Iterator<Item = Artifact<'_>> {
+        let client = client.clone();
+
+        self.artifacts
+            .iter()
+            .map(move |a| Artifact::new(a, self, client.clone()))
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct Metadata {
     key: String,
@@ -99,18 +171,21 @@ struct Metadata {
 }
 
 #[derive(Debug, Deserialize)]
-struct ArtifactInternal {
+pub(crate) struct ArtifactInternal {
     filename: String,
     hashes: Hashes,
-    size: u32,
+    size: u64,
     #[serde(rename = "_links")]
     links: Links,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 struct Hashes {
+    #[cfg_attr(not(feature = "hash-sha1"), allow(dead_code))]
     sha1: String,
+    #[cfg_attr(not(feature = "hash-md5"), allow(dead_code))]
     md5: String,
+    #[cfg_attr(not(feature = "hash-sha256"), allow(dead_code))]
     sha256: String,
 }
 
@@ -203,26 +278,45 @@ struct Links {
     https: Option<Download>,
 }
 
-#[derive(Debug, Deserialize)]
-struct ActionHistory {
+/// The history of an action, as previously reported by the target,
+/// see [`UpdatePreFetch::fetch_with_history`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct ActionHistory {
     status: String,
     #[serde(default)]
     messages: Vec<String>,
 }
 
+impl ActionHistory {
+    /// The current status of the action on the server, such as `RUNNING`.
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    /// The messages previously sent by the target as feedback about the action.
+    pub fn messages(&self) -> &[String] {
+        &self.messages
+    }
+}
+
 /// A pending update to deploy.
 #[derive(Debug)]
 pub struct Update {
-    client: Client,
+    client: HttpClient,
     info: Reply,
     url: String,
 }
 
 impl Update {
-    fn new(client: Client, info: Reply, url: String) -> Self {
+    fn new(client: HttpClient, info: Reply, url: String) -> Self {
         Self { client, info, url }
     }
 
+    /// The id of the action.
+    pub fn id(&self) -> &str {
+        &self.info.id
+    }
+
     /// Handling for the download part of the provisioning process.
     pub fn download_type(&self) -> Type {
         self.info.deployment.download
@@ -238,26 +332,38 @@ impl Update {
         self.info.deployment.maintenance_window
     }
 
-    /// An iterator on all the software chunks of the update.
-    pub fn chunks(&self) -> impl Iterator<Item = Chunk> {
-        let client = self.client.clone();
+    /// The history of the action, only provided by the server if requested using
+    /// [`UpdatePreFetch::fetch_with_history`].
+    pub fn action_history(&self) -> Option<&ActionHistory> {
+        self.info.action_history.as_ref()
+    }
 
-        self.info
-            .deployment
-            .chunks
-            .iter()
-            .map(move |c| Chunk::new(c, client.clone()))
+    /// An iterator on all the software chunks of the update.
+    pub fn chunks(&self) -> impl Iterator<Item = Chunk<'_>> {
+        self.info.deployment.chunks(&self.client)
     }
 
     /// Download all software chunks to the directory defined in `dir`.
     pub async fn download(&self, dir: &Path) -> Result<Vec<DownloadedArtifact>, Error> {
-        let mut result = Vec::new();
-        for c in self.chunks() {
-            let downloaded = c.download(dir).await?;
-            result.extend(downloaded);
-        }
+        self.download_with_options(dir, &DownloadOptions::default())
+            .await
+    }
 
-        Ok(result)
+    /// Download all software chunks to the directory defined in `dir`, using `options`.
+    pub async fn download_with_options(
+        &self,
+        dir: &Path,
+        options: &DownloadOptions,
+    ) -> Result<Vec<DownloadedArtifact>, Error> {
+        let chunks: Vec<Chunk> = self.chunks().collect();
+        download_chunks(&chunks, dir, options, Some(self)).await
+    }
+
+    /// Return the state of this update in `phase`, which can be saved to report
+    /// the result of the update after a reboot.
+    pub fn action_state(&self, phase: Phase) -> Result<ActionState, Error> {
+        let url = feedback_url(&self.url)?;
+        Ok(ActionState::new(&self.info.id, url.as_str(), phase))
     }
 
     /// Send feedback to server about this update, with custom progress information.
@@ -267,6 +373,9 @@ impl Update {
     /// * `finished`: defined status of the result. The action will be kept open on the server until the controller on the device reports either [`Finished::Success`] or [`Finished::Failure`].
     /// * `progress`: progress assumption of the device.
     /// * `details`: list of details message information.
+    ///
+    /// If the client uses a feedback journal, see [`ClientBuilder::feedback_journal`](crate::ddi::ClientBuilder::feedback_journal),
+    /// the feedback is queued if the server cannot be reached.
     pub async fn send_feedback_with_progress<T: Serialize>(
         &self,
         execution: Execution,
@@ -286,6 +395,25 @@ impl Update {
         .await
     }
 
+    /// Send the download `progress` of this update, without retrying it nor queueing it
+    /// in the feedback journal as it is soon outdated.
+    pub(crate) async fn send_download_progress(&self, progress: Progress) -> Result<(), Error> {
+        let url = feedback_url(&self.url)?;
+        let details = vec!["Downloading".to_string()];
+        let feedback = Feedback::new(
+            &self.info.id,
+            Execution::Proceeding,
+            Finished::None,
+            Some(progress),
+            details,
+        );
+
+        self.client
+            .send_once(self.client.post(url).json(&feedback))
+            .await?;
+        Ok(())
+    }
+
     /// Send feedback to server about this update.
     ///
     /// Same as [`Update::send_feedback_with_progress`] but without passing custom progress information about the update.
@@ -306,17 +434,28 @@ impl Update {
         )
         .await
     }
+
+    /// Build feedback about this update, with typed progress and optional status code
+    /// and timestamp, then send it using [`FeedbackBuilder::send`].
+    ///
+    /// # Arguments
+    /// * `execution`: status of the action execution.
+    /// * `finished`: defined status of the result. The action will be kept open on the server until the controller on the device reports either [`Finished::Success`] or [`Finished::Failure`].
+    pub fn feedback(&self, execution: Execution, finished: Finished) -> FeedbackBuilder<'_> {
+        let url = feedback_url(&self.url);
+        FeedbackBuilder::new(&self.client, url, &self.info.id, execution, finished)
+    }
 }
 
 /// Software chunk of an update.
 #[derive(Debug)]
 pub struct Chunk<'a> {
     chunk: &'a ChunkInternal,
-    client: Client,
+    client: HttpClient,
 }
 
 impl<'a> Chunk<'a> {
-    fn new(chunk: &'a ChunkInternal, client: Client) -> Self {
+    fn new(chunk: &'a ChunkInternal, client: HttpClient) -> Self {
         Self { chunk, client }
     }
 
@@ -325,6 +464,12 @@ impl<'a> Chunk<'a> {
         &self.chunk.part
     }
 
+    /// Id of the software module of the chunk, if provided by the server,
+    /// see [`Client::software_module_artifacts`](crate::ddi::Client::software_module_artifacts).
+    pub fn id(&self) -> Option<u64> {
+        self.chunk.id
+    }
+
     /// Name of the chunk.
     pub fn name(&self) -> &str {
         &self.chunk.name
@@ -336,13 +481,8 @@ impl<'a> Chunk<'a> {
     }
 
     /// An iterator on all the artifacts of the chunk.
-    pub fn artifacts(&self) -> impl Iterator<Item = Artifact> {
-        let client = self.client.clone();
-
-        self.chunk
-            .artifacts
-            .iter()
-            .map(move |a| Artifact::new(a, client.clone()))
+    pub fn artifacts(&self) -> impl Iterator<Item = Artifact<'_>> {
+        self.chunk.artifacts(&self.client)
     }
 
     /// An iterator on all the metadata of the chunk.
@@ -355,16 +495,31 @@ impl<'a> Chunk<'a> {
 
     /// Download all artifacts of the chunk to the directory defined in `dir`.
     pub async fn download(&'a self, dir: &Path) -> Result<Vec<DownloadedArtifact>, Error> {
-        let mut dir = dir.to_path_buf();
-        dir.push(self.name());
-        let mut result = Vec::new();
+        self.download_with_options(dir, &DownloadOptions::default())
+            .await
+    }
 
-        for a in self.artifacts() {
-            let downloaded = a.download(&dir).await?;
-            result.push(downloaded);
-        }
+    /// Download all artifacts of the chunk to the directory defined in `dir`, using `options`.
+    pub async fn download_with_options(
+        &'a self,
+        dir: &Path,
+        options: &DownloadOptions,
+    ) -> Result<Vec<DownloadedArtifact>, Error> {
+        let chunk_dir = self.download_dir(dir)?;
+        let artifacts: Vec<_> = self.artifacts().map(|a| (chunk_dir.clone(), a)).collect();
+
+        let progress = ProgressTracker::new(options, self.size());
+        download_artifacts(&artifacts, options, &progress).await
+    }
+
+    /// The directory, in `dir`, the artifacts of the chunk are downloaded to.
+    fn download_dir(&self, dir: &Path) -> Result<PathBuf, Error> {
+        Ok(dir.join(check_file_name(self.name())?))
+    }
 
-        Ok(result)
+    /// Total size of the artifacts of the chunk, in bytes.
+    fn size(&self) -> u64 {
+        self.artifacts().map(|a| a.size()).sum()
     }
 }
 
@@ -372,12 +527,18 @@ impl<'a> Chunk<'a> {
 #[derive(Debug)]
 pub struct Artifact<'a> {
     artifact: &'a ArtifactInternal,
-    client: Client,
+    #[cfg_attr(not(feature = "signature"), allow(dead_code))]
+    chunk: &'a ChunkInternal,
+    client: HttpClient,
 }
 
 impl<'a> Artifact<'a> {
-    fn new(artifact: &'a ArtifactInternal, client: Client) -> Self {
-        Self { artifact, client }
+    fn new(artifact: &'a ArtifactInternal, chunk: &'a ChunkInternal, client: HttpClient) -> Self {
+        Self {
+            artifact,
+            chunk,
+            client,
+        }
     }
 
     /// The name of the file.
@@ -385,50 +546,360 @@ impl<'a> Artifact<'a> {
         &self.artifact.filename
     }
 
-    /// The size of the file.
-    pub fn size(&self) -> u32 {
+    /// The size of the file, in bytes.
+    pub fn size(&self) -> u64 {
         self.artifact.size
     }
 
-    async fn download_response(&'a self) -> Result<Response, Error> {
-        let download = self
-            .artifact
-            .links
-            .https
-            .as_ref()
-            .or_else(|| self.artifact.links.http.as_ref())
-            .expect("Missing content link in for artifact");
+    /// The URL to download the artifact over HTTPS, if provided by the server.
+    pub fn https_url(&self) -> Option<&str> {
+        self.artifact.links.https.as_ref().map(|d| d.content.href())
+    }
 
-        let resp = self
-            .client
-            .get(&download.content.to_string())
-            .send()
-            .await?;
+    /// The URL to download the artifact over HTTP, if provided by the server.
+    pub fn http_url(&self) -> Option<&str> {
+        self.artifact.links.http.as_ref().map(|d| d.content.href())
+    }
+
+    /// The download links of the artifact allowed by the [`LinkPolicy`] of the client,
+    /// by order of preference.
+    fn downloads(&self) -> Vec<&Download> {
+        let links = &self.artifact.links;
+        let downloads = match self.client.link_policy() {
+            LinkPolicy::HttpsOnly => [links.https.as_ref(), None],
+            LinkPolicy::PreferHttps => [links.https.as_ref(), links.http.as_ref()],
+            LinkPolicy::HttpOnly => [links.http.as_ref(), None],
+        };
+
+        downloads.iter().flatten().copied().collect()
+    }
+
+    /// The name of the downloaded file, which is guaranteed to be a safe file name.
+    fn local_file_name(&self, options: &DownloadOptions) -> Result<&str, Error> {
+        match options.file_naming {
+            FileNaming::Filename => check_file_name(options.local_file_name(self.filename())),
+            FileNaming::Sha256 => {
+                let hash = &self.artifact.hashes.sha256;
+                if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(Error::InvalidFileName(hash.to_string()));
+                }
+                Ok(hash)
+            }
+        }
+    }
+
+    /// Request the content of the artifact, starting from `offset` if not `0`.
+    async fn download_response(&'a self, offset: u64) -> Result<Response, Error> {
+        let mut downloads = self.downloads().into_iter().peekable();
+
+        while let Some(download) = downloads.next() {
+            let mut request = self.client.get(download.content.to_string());
+            if offset > 0 {
+                request = request.header(RANGE, format!("bytes={}-", offset));
+            }
 
-        resp.error_for_status_ref()?;
-        Ok(resp)
+            match self.client.send(request).await {
+                // try the next link if the server cannot be reached using this one
+                Err(e) if downloads.peek().is_some() && is_unreachable(&e) => continue,
+                result => return result,
+            }
+        }
+
+        Err(Error::MissingLink(self.filename().to_string()))
+    }
+
+    /// Retrieve the md5sum of the artifact from the md5sum file provided by the server,
+    /// or `None` if the server does not provide one.
+    ///
+    /// This can be used to cross-check the artifact, such as when it cannot be verified
+    /// while downloading because the `hash-md5` feature is disabled.
+    pub async fn fetch_md5sum(&self) -> Result<Option<String>, Error> {
+        let link = match self
+            .downloads()
+            .into_iter()
+            .find_map(|download| download.md5sum.as_ref())
+        {
+            Some(link) => link,
+            None => return Ok(None),
+        };
+
+        let request = self.client.get(link.to_string());
+        let content = self.client.send(request).await?.text().await?;
+
+        parse_md5sum(&content)
+            .map(Some)
+            .ok_or(Error::InvalidMd5sum(content))
+    }
+
+    /// Request the content of the artifact, resuming from `offset` if the server supports it.
+    /// Return the response and if it contains the content from `offset`, or from the start.
+    async fn download_response_from(&'a self, offset: u64) -> Result<(Response, bool), Error> {
+        if offset == 0 {
+            return Ok((self.download_response(0).await?, false));
+        }
+
+        match self.download_response(offset).await {
+            Ok(resp) if resp.status() == StatusCode::PARTIAL_CONTENT => {
+                if range_start(&resp) == Some(offset) {
+                    Ok((resp, true))
+                } else {
+                    // not the expected range, download the whole file instead
+                    Ok((self.download_response(0).await?, false))
+                }
+            }
+            // server does not support range requests and sent the whole file
+            Ok(resp) => Ok((resp, false)),
+            Err(e) if e.status() == Some(StatusCode::RANGE_NOT_SATISFIABLE) => {
+                Ok((self.download_response(0).await?, false))
+            }
+            Err(e) => Err(e),
+        }
     }
 
     /// Download the artifact file to the directory defined in `dir`.
+    ///
+    /// The file is first downloaded to a temporary `.part` file, which is only renamed
+    /// once complete. Use [`Artifact::download_with_options`] to also verify its
+    /// checksums while downloading, see [`DownloadOptions::verification`].
+    /// The temporary file is removed if the download fails.
     pub async fn download(&'a self, dir: &Path) -> Result<DownloadedArtifact, Error> {
-        let mut resp = self.download_response().await?;
+        self.download_with_options(dir, &DownloadOptions::default())
+            .await
+    }
 
+    /// Download the artifact file to the directory defined in `dir`, using `options`.
+    pub async fn download_with_options(
+        &'a self,
+        dir: &Path,
+        options: &DownloadOptions,
+    ) -> Result<DownloadedArtifact, Error> {
+        let progress = ProgressTracker::new(options, self.size());
+        self.download_internal(dir, options, &progress).await
+    }
+
+    async fn download_internal(
+        &self,
+        dir: &Path,
+        options: &DownloadOptions,
+        progress: &ProgressTracker<'_>,
+    ) -> Result<DownloadedArtifact, Error> {
         if !dir.exists() {
             DirBuilder::new().recursive(true).create(dir).await?;
         }
 
         let mut file_name = dir.to_path_buf();
-        file_name.push(self.filename());
-        let mut dest = File::create(&file_name).await?;
+        file_name.push(self.local_file_name(options)?);
+        // download to a temporary file which is renamed once complete and verified,
+        // so an incomplete file is never left with the final name.
+        // The partial file is kept if it can be resumed later, including when the
+        // download is canceled by dropping this future.
+        let mut part = PartFile::new(part_file_name(&file_name), options.resumable());
+
+        let downloaded = match self.download_part(&part.path, options, progress).await {
+            Ok(downloaded) => downloaded,
+            Err(e) => {
+                if is_corrupted(&e) {
+                    part.keep = false;
+                }
+                return Err(e);
+            }
+        };
+
+        tokio::fs::rename(&part.path, &file_name).await?;
+        part.keep = true;
 
-        while let Some(chunk) = resp.chunk().await? {
+        Ok(DownloadedArtifact {
+            file: file_name,
+            ..downloaded
+        })
+    }
+
+    /// Download the artifact to `part` and verify it.
+    async fn download_part(
+        &self,
+        part: &Path,
+        options: &DownloadOptions,
+        progress: &ProgressTracker<'_>,
+    ) -> Result<DownloadedArtifact, Error> {
+        // size of the partial file left by a previous attempt, if any
+        let offset = match tokio::fs::metadata(part).await {
+            Ok(metadata) if options.resumable() && metadata.len() <= self.size() => metadata.len(),
+            _ => 0,
+        };
+
+        let (mut resp, resumed) = if offset > 0 && offset == self.size() {
+            // the previous attempt got the whole file, it only has to be verified
+            (None, true)
+        } else {
+            let (resp, resumed) = self.download_response_from(offset).await?;
+            (Some(resp), resumed)
+        };
+        let dest = if resumed {
+            OpenOptions::new().append(true).open(part).await?
+        } else {
+            File::create(part).await?
+        };
+        let offset = if resumed { offset } else { 0 };
+        let mut progress = progress
+            .start_artifact(self.filename(), self.size(), offset)
+            .await;
+
+        let mut verifier = Verifier::new(&options.verification, &self.artifact.hashes);
+        #[cfg(feature = "signature")]
+        let signature = self.required_signature(options).await?;
+        #[cfg(feature = "signature")]
+        let mut signature_check = match (&options.trusted_keys, &signature) {
+            (Some(keys), Some(signature)) => Some(SignatureCheck::new(keys, signature)?),
+            _ => None,
+        };
+
+        if resumed {
+            // the data already downloaded have to be verified as well
+            read_file(part, |data| {
+                verifier.update(data);
+                #[cfg(feature = "signature")]
+                if let Some(check) = &mut signature_check {
+                    check.update(data);
+                }
+            })
+            .await?;
+        }
+
+        // the first data are used to detect the compression format, if needed
+        let mut next = next_chunk(&mut resp).await?;
+        let mut dest = ArtifactWriter::new(
+            dest,
+            options,
+            self.filename(),
+            next.as_deref().unwrap_or_default(),
+        );
+
+        let mut received = offset;
+        while let Some(chunk) = next {
+            received += chunk.len() as u64;
+            // do not write more than announced, the server may be sending garbage
+            if received > self.size() {
+                return Err(Error::SizeMismatch {
+                    expected: self.size(),
+                    received,
+                });
+            }
             dest.write_all(&chunk).await?;
+            verifier.update(&chunk);
+            #[cfg(feature = "signature")]
+            if let Some(check) = &mut signature_check {
+                check.update(&chunk);
+            }
+            progress.advance(chunk.len() as u64).await;
+            self.client.rate_limiter().throttle(chunk.len()).await;
+            next = next_chunk(&mut resp).await?;
+        }
+        // make sure all the data are on disk before the file is used
+        let dest = dest.finish().await?;
+        dest.sync_all().await?;
+        drop(dest);
+
+        if received != self.size() {
+            return Err(Error::SizeMismatch {
+                expected: self.size(),
+                received,
+            });
+        }
+        verifier.finalize()?;
+
+        #[allow(unused_mut)]
+        let mut downloaded =
+            DownloadedArtifact::new(part.to_path_buf(), self.artifact.hashes.clone());
+        #[cfg(feature = "signature")]
+        if let Some(check) = signature_check {
+            downloaded.signature = Some(check.finalize()?);
         }
 
-        Ok(DownloadedArtifact::new(
-            file_name,
-            self.artifact.hashes.clone(),
-        ))
+        Ok(downloaded)
+    }
+
+    /// Provide a `Stream` of `Bytes` to download the artifact while verifying its
+    /// signature using `keys`, see [`TrustedKeys`].
+    ///
+    /// The stream will yield the same data as [`Artifact::download_stream`] but will raise
+    /// an error if the signature of the downloaded data is not valid.
+    #[cfg(feature = "signature")]
+    pub async fn download_stream_with_signature_check(
+        &'a self,
+        keys: &TrustedKeys,
+    ) -> Result<impl Stream<Item = Result<Bytes, Error>>, Error> {
+        let signature = self
+            .signature()
+            .await?
+            .ok_or_else(|| Error::MissingSignature(self.filename().to_string()))?;
+        let stream = self.download_stream().await?;
+
+        Ok(verify_stream(stream, keys.clone(), signature))
+    }
+
+    /// The name of the artifact or chunk metadata providing the signature of this artifact.
+    #[cfg(feature = "signature")]
+    fn signature_name(&self) -> String {
+        format!("{}.sig", self.filename())
+    }
+
+    /// Return `true` if this artifact is the signature of another artifact of the chunk.
+    #[cfg(feature = "signature")]
+    fn is_signature(&self) -> bool {
+        self.chunk
+            .artifacts
+            .iter()
+            .any(|a| format!("{}.sig", a.filename) == self.filename())
+    }
+
+    /// Retrieve the signature of the artifact, if provided by the server.
+    #[cfg(feature = "signature")]
+    async fn signature(&self) -> Result<Option<Signature>, Error> {
+        // signatures are small, do not download anything else
+        const MAX_SIGNATURE_SIZE: u64 = 4096;
+
+        let name = self.signature_name();
+
+        if let Some(artifact) = self.chunk.artifacts.iter().find(|a| a.filename == name) {
+            let artifact = Artifact::new(artifact, self.chunk, self.client.clone());
+            if artifact.size() > MAX_SIGNATURE_SIZE {
+                return Err(Error::SignatureTooLarge(name));
+            }
+
+            // do not trust the announced size
+            let mut response = artifact.download_response(0).await?;
+            let mut data = Vec::new();
+            while let Some(chunk) = response.chunk().await? {
+                if (data.len() + chunk.len()) as u64 > MAX_SIGNATURE_SIZE {
+                    return Err(Error::SignatureTooLarge(name));
+                }
+                data.extend_from_slice(&chunk);
+            }
+            let data = String::from_utf8_lossy(&data);
+
+            return decode_signature(&data).map(Some);
+        }
+
+        match self.chunk.metadata.iter().find(|m| m.key == name) {
+            Some(metadata) => decode_signature(&metadata.value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The signature to verify according to `options`, if any.
+    #[cfg(feature = "signature")]
+    async fn required_signature(
+        &self,
+        options: &DownloadOptions,
+    ) -> Result<Option<Signature>, Error> {
+        if options.trusted_keys.is_none() || self.is_signature() {
+            return Ok(None);
+        }
+
+        match self.signature().await? {
+            Some(signature) => Ok(Some(signature)),
+            None => Err(Error::MissingSignature(self.filename().to_string())),
+        }
     }
 
     /// Provide a `Stream` of `Bytes` to download the artifact.
@@ -439,9 +910,46 @@ impl<'a> Artifact<'a> {
     pub async fn download_stream(
         &'a self,
     ) -> Result<impl Stream<Item = Result<Bytes, Error>>, Error> {
-        let resp = self.download_response().await?;
+        let resp = self.download_response(0).await?;
+        let rate_limiter = self.client.rate_limiter().clone();
+
+        let stream = resp
+            .bytes_stream()
+            .map_err(Error::from)
+            .and_then(move |data| {
+                let rate_limiter = rate_limiter.clone();
+                async move {
+                    rate_limiter.throttle(data.len()).await;
+                    Ok(data)
+                }
+            });
 
-        Ok(resp.bytes_stream().map_err(|e| e.into()))
+        Ok(Box::pin(stream))
+    }
+
+    /// Provide a `Stream` of `Bytes` to download the artifact, decompressing it on the fly
+    /// according to `decompression`.
+    ///
+    /// The size and all the checksums supported by the enabled features are verified
+    /// on the compressed data sent by the server: the stream raises an error at the end
+    /// if they do not match.
+    #[cfg(feature = "decompress")]
+    pub async fn download_stream_decompressed(
+        &'a self,
+        decompression: Decompression,
+    ) -> Result<impl Stream<Item = Result<Bytes, Error>>, Error> {
+        let stream = self.download_stream().await?;
+        let stream = VerifiedStream {
+            stream: Box::pin(stream),
+            verifier: Some(Verifier::new(
+                &VerificationPolicy::AllAvailable,
+                &self.artifact.hashes,
+            )),
+            size: self.size(),
+            received: 0,
+        };
+
+        decompress_stream(stream, decompression, self.filename()).await
     }
 
     /// Provide a `Stream` of `Bytes` to download the artifact while checking md5 checksum.
@@ -502,25 +1010,218 @@ impl<'a> Artifact<'a> {
     }
 }
 
+/// Return `true` if `error` means the downloaded data are corrupted.
+fn is_corrupted(error: &Error) -> bool {
+    match error {
+        // a short read can be resumed, but not a file larger than announced
+        Error::SizeMismatch { expected, received } => received > expected,
+        #[cfg(feature = "hash-digest")]
+        Error::ChecksumError(_) => true,
+        #[cfg(feature = "signature")]
+        Error::InvalidSignature(_) => true,
+        _ => false,
+    }
+}
+
+/// Download all the artifacts of `chunks` to the directory defined in `dir`, reporting
+/// the progress of `update`, if any, according to `options`.
+pub(crate) async fn download_chunks(
+    chunks: &[Chunk<'_>],
+    dir: &Path,
+    options: &DownloadOptions,
+    update: Option<&Update>,
+) -> Result<Vec<DownloadedArtifact>, Error> {
+    let mut artifacts = Vec::new();
+    for c in chunks {
+        let chunk_dir = c.download_dir(dir)?;
+        artifacts.extend(c.artifacts().map(|a| (chunk_dir.clone(), a)));
+    }
+
+    let total = chunks.iter().map(|c| c.size()).sum();
+    match update.filter(|_| options.progress_feedback.is_some()) {
+        Some(update) => {
+            let (progress, feedback) = ProgressTracker::with_feedback(options, total);
+            // the tracker is dropped once downloaded, stopping the feedback once the
+            // last progress has been sent
+            let download = async move { download_artifacts(&artifacts, options, &progress).await };
+            let (result, ()) =
+                future::join(download, send_progress_feedback(update, feedback)).await;
+            result
+        }
+        None => {
+            let progress = ProgressTracker::new(options, total);
+            download_artifacts(&artifacts, options, &progress).await
+        }
+    }
+}
+
+/// Download the `artifacts`, each one to its directory, up to
+/// [`DownloadOptions::max_concurrent_downloads`] at the same time.
+async fn download_artifacts(
+    artifacts: &[(PathBuf, Artifact<'_>)],
+    options: &DownloadOptions,
+    progress: &ProgressTracker<'_>,
+) -> Result<Vec<DownloadedArtifact>, Error> {
+    // the pending transfers are dropped, and so canceled, on the first error
+    stream::iter(artifacts)
+        .map(|(dir, artifact)| artifact.download_internal(dir, options, progress))
+        .buffered(options.concurrency())
+        .try_collect()
+        .await
+}
+
+/// Stream checking the size and checksums of the artifact data, raising an error
+/// at the end of the stream if they do not match.
+#[cfg(feature = "decompress")]
+struct VerifiedStream {
+    stream: Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send>>,
+    // `None` once verified
+    verifier: Option<Verifier>,
+    size: u64,
+    received: u64,
+}
+
+#[cfg(feature = "decompress")]
+impl Stream for VerifiedStream {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let me = Pin::into_inner(self);
+
+        match me.stream.as_mut().poll_next(cx) {
+            Poll::Ready(Some(Ok(data))) => {
+                me.received += data.len() as u64;
+                if me.received > me.size {
+                    me.verifier = None;
+                    return Poll::Ready(Some(Err(Error::SizeMismatch {
+                        expected: me.size,
+                        received: me.received,
+                    })));
+                }
+                if let Some(verifier) = &mut me.verifier {
+                    verifier.update(&data);
+                }
+                Poll::Ready(Some(Ok(data)))
+            }
+            Poll::Ready(None) => match me.verifier.take() {
+                Some(_) if me.received != me.size => Poll::Ready(Some(Err(Error::SizeMismatch {
+                    expected: me.size,
+                    received: me.received,
+                }))),
+                Some(verifier) => Poll::Ready(verifier.finalize().err().map(Err)),
+                None => Poll::Ready(None),
+            },
+            poll => poll,
+        }
+    }
+}
+
+/// Temporary file an artifact is downloaded to, removed when dropped unless `keep` is set.
+struct PartFile {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl PartFile {
+    fn new(path: PathBuf, keep: bool) -> Self {
+        Self { path, keep }
+    }
+}
+
+impl Drop for PartFile {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Return the path of the temporary file used while downloading `file_name`.
+fn part_file_name(file_name: &Path) -> PathBuf {
+    let mut part = file_name.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Return `true` if `error` is caused by the server not being reachable, or failing,
+/// rather than by the request itself.
+fn is_unreachable(error: &Error) -> bool {
+    match error {
+        Error::ReqwestError(e) => e.status().is_none(),
+        Error::ServerFailure(_) => true,
+        _ => false,
+    }
+}
+
+/// Return the first byte position of the `Content-Range` of `resp`, if any.
+fn range_start(resp: &Response) -> Option<u64> {
+    let range = resp.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = range.strip_prefix("bytes ")?;
+    let (start, _) = range.split_once('-')?;
+
+    start.trim().parse().ok()
+}
+
+/// Read the next chunk of `resp`, if any is left to be downloaded.
+async fn next_chunk(resp: &mut Option<Response>) -> Result<Option<Bytes>, Error> {
+    match resp {
+        Some(resp) => Ok(resp.chunk().await?),
+        None => Ok(None),
+    }
+}
+
+/// Parse the content of a md5sum file, in the `md5sum` output format, `<hash>  <file name>`,
+/// returning the hash.
+fn parse_md5sum(content: &str) -> Option<String> {
+    let hash = content.split_whitespace().next()?;
+
+    if hash.len() == 32 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(hash.to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
 /// A downloaded file part of a [`Chunk`].
 #[derive(Debug)]
 pub struct DownloadedArtifact {
     file: PathBuf,
+    #[cfg_attr(not(feature = "hash-digest"), allow(dead_code))]
     hashes: Hashes,
+    #[cfg(feature = "signature")]
+    signature: Option<VerifiedSignature>,
+}
+
+const HASH_BUFFER_SIZE: usize = 4096;
+
+/// Pass the content of the file at `path` to `update`, such as the data already
+/// downloaded when resuming a download.
+async fn read_file(path: &Path, mut update: impl FnMut(&[u8])) -> Result<(), Error> {
+    use tokio::io::AsyncReadExt;
+
+    let mut file = File::open(path).await?;
+    let mut buffer = [0; HASH_BUFFER_SIZE];
+
+    loop {
+        let n = file.read(&mut buffer[..]).await?;
+        if n == 0 {
+            break;
+        }
+        update(&buffer[..n]);
+    }
+
+    Ok(())
 }
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "hash-digest")] {
-        use std::{
-            pin::Pin,
-            task::Poll,
-        };
         use digest::Digest;
 
-        const HASH_BUFFER_SIZE: usize = 4096;
-
         /// Enum representing the different type of supported checksums
-        #[derive(Debug, strum::Display, Clone)]
+        #[derive(Debug, strum::Display, Clone, Copy, PartialEq)]
         pub enum ChecksumType {
             /// md5
             #[cfg(feature = "hash-md5")]
@@ -645,12 +1346,106 @@ cfg_if::cfg_if! {
                 }
             }
         }
+
+        /// Hash the data of an artifact while it is downloaded to check them according
+        /// to a [`VerificationPolicy`].
+        struct Verifier {
+            #[cfg(feature = "hash-md5")]
+            md5: Option<DownloadHasher<md5::Md5>>,
+            #[cfg(feature = "hash-sha1")]
+            sha1: Option<DownloadHasher<sha1::Sha1>>,
+            #[cfg(feature = "hash-sha256")]
+            sha256: Option<DownloadHasher<sha2::Sha256>>,
+        }
+
+        impl Verifier {
+            fn new(policy: &VerificationPolicy, hashes: &Hashes) -> Self {
+                let enabled = |checksum: ChecksumType| match policy {
+                    VerificationPolicy::None => false,
+                    VerificationPolicy::AllAvailable => true,
+                    VerificationPolicy::Require(required) => *required == checksum,
+                };
+
+                Self {
+                    #[cfg(feature = "hash-md5")]
+                    md5: if enabled(ChecksumType::Md5) {
+                        Some(DownloadHasher::new_md5(hashes.md5.clone()))
+                    } else {
+                        None
+                    },
+                    #[cfg(feature = "hash-sha1")]
+                    sha1: if enabled(ChecksumType::Sha1) {
+                        Some(DownloadHasher::new_sha1(hashes.sha1.clone()))
+                    } else {
+                        None
+                    },
+                    #[cfg(feature = "hash-sha256")]
+                    sha256: if enabled(ChecksumType::Sha256) {
+                        Some(DownloadHasher::new_sha256(hashes.sha256.clone()))
+                    } else {
+                        None
+                    },
+                }
+            }
+
+            fn update(&mut self, data: &[u8]) {
+                #[cfg(feature = "hash-md5")]
+                if let Some(hasher) = &mut self.md5 {
+                    hasher.update(data);
+                }
+                #[cfg(feature = "hash-sha1")]
+                if let Some(hasher) = &mut self.sha1 {
+                    hasher.update(data);
+                }
+                #[cfg(feature = "hash-sha256")]
+                if let Some(hasher) = &mut self.sha256 {
+                    hasher.update(data);
+                }
+            }
+
+            fn finalize(self) -> Result<(), Error> {
+                #[cfg(feature = "hash-md5")]
+                if let Some(hasher) = self.md5 {
+                    hasher.finalize()?;
+                }
+                #[cfg(feature = "hash-sha1")]
+                if let Some(hasher) = self.sha1 {
+                    hasher.finalize()?;
+                }
+                #[cfg(feature = "hash-sha256")]
+                if let Some(hasher) = self.sha256 {
+                    hasher.finalize()?;
+                }
+
+                Ok(())
+            }
+        }
+    } else {
+        // no checksum can be verified without any hash feature
+        struct Verifier;
+
+        impl Verifier {
+            fn new(_policy: &VerificationPolicy, _hashes: &Hashes) -> Self {
+                Self
+            }
+
+            fn update(&mut self, _data: &[u8]) {}
+
+            fn finalize(self) -> Result<(), Error> {
+                Ok(())
+            }
+        }
     }
 }
 
-impl<'a> DownloadedArtifact {
+impl DownloadedArtifact {
     fn new(file: PathBuf, hashes: Hashes) -> Self {
-        Self { file, hashes }
+        Self {
+            file,
+            hashes,
+            #[cfg(feature = "signature")]
+            signature: None,
+        }
     }
 
     /// Path of the downloaded file.
@@ -658,6 +1453,13 @@ impl<'a> DownloadedArtifact {
         &self.file
     }
 
+    /// The signature of the artifact, if it has been verified,
+    /// see [`DownloadOptions::verify_signatures`].
+    #[cfg(feature = "signature")]
+    pub fn signature(&self) -> Option<&VerifiedSignature> {
+        self.signature.as_ref()
+    }
+
     #[cfg(feature = "hash-digest")]
     async fn hash<T>(&self, mut hasher: DownloadHasher<T>) -> Result<(), Error>
     where
@@ -665,19 +1467,7 @@ impl<'a> DownloadedArtifact {
         <T as Digest>::OutputSize: core::ops::Add,
         <<T as Digest>::OutputSize as core::ops::Add>::Output: generic_array::ArrayLength<u8>,
     {
-        use tokio::io::AsyncReadExt;
-
-        let mut file = File::open(&self.file).await?;
-        let mut buffer = [0; HASH_BUFFER_SIZE];
-
-        loop {
-            let n = file.read(&mut buffer[..]).await?;
-            if n == 0 {
-                break;
-            }
-            hasher.update(&buffer[..n]);
-        }
-
+        read_file(&self.file, |data| hasher.update(data)).await?;
         hasher.finalize()
     }
 
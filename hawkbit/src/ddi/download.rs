@@ -0,0 +1,549 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// Options used when downloading artifacts
+
+use std::fmt;
+use std::path::{Component, Path};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{watch, Mutex};
+
+use crate::ddi::client::Error;
+#[cfg(feature = "decompress")]
+use crate::ddi::decompress::{decompressed_file_name, Compression, Decompression};
+#[cfg(feature = "hash-digest")]
+use crate::ddi::deployment_base::ChecksumType;
+use crate::ddi::deployment_base::Update;
+use crate::ddi::feedback::Progress;
+#[cfg(feature = "signature")]
+use crate::ddi::signature::TrustedKeys;
+
+type ProgressCallback = Arc<dyn Fn(&DownloadProgress) + Send + Sync>;
+
+/// Options customizing how artifacts are downloaded, see
+/// [`Artifact::download_with_options`](crate::ddi::Artifact::download_with_options).
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use hawkbit::ddi::{DownloadOptions, Update};
+///
+/// # async fn download(update: Update) -> Result<(), hawkbit::ddi::Error> {
+/// let options = DownloadOptions::default()
+///     .resume(true)
+///     .progress(|p| println!("{}: {}/{}", p.artifact(), p.downloaded(), p.total()));
+/// let artifacts = update
+///     .download_with_options(Path::new("./download/"), &options)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct DownloadOptions {
+    pub(crate) resume: bool,
+    pub(crate) file_naming: FileNaming,
+    pub(crate) verification: VerificationPolicy,
+    max_concurrent_downloads: usize,
+    #[cfg(feature = "decompress")]
+    decompression: Option<Decompression>,
+    #[cfg(feature = "signature")]
+    pub(crate) trusted_keys: Option<TrustedKeys>,
+    progress: Option<ProgressCallback>,
+    pub(crate) progress_feedback: Option<Duration>,
+}
+
+impl fmt::Debug for DownloadOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("DownloadOptions");
+        debug
+            .field("resume", &self.resume)
+            .field("file_naming", &self.file_naming)
+            .field("verification", &self.verification)
+            .field("max_concurrent_downloads", &self.max_concurrent_downloads);
+        #[cfg(feature = "decompress")]
+        debug.field("decompression", &self.decompression);
+        #[cfg(feature = "signature")]
+        debug.field("trusted_keys", &self.trusted_keys);
+        debug
+            .field("progress", &self.progress.is_some())
+            .field("progress_feedback", &self.progress_feedback)
+            .finish()
+    }
+}
+
+impl DownloadOptions {
+    /// Resume the download of artifacts partially downloaded by a previous attempt,
+    /// using HTTP `Range` requests, instead of downloading them again from the start.
+    ///
+    /// The partial `.part` file is kept when a download fails, unless its checksum
+    /// is invalid, and is downloaded from scratch if the server does not support
+    /// range requests. Disabled by default.
+    pub fn resume(self, resume: bool) -> Self {
+        let mut options = self;
+        options.resume = resume;
+        options
+    }
+
+    /// Set how the downloaded files are named, default to [`FileNaming::Filename`].
+    pub fn file_naming(self, file_naming: FileNaming) -> Self {
+        let mut options = self;
+        options.file_naming = file_naming;
+        options
+    }
+
+    /// Set which checksums are verified while downloading, default to
+    /// [`VerificationPolicy::None`].
+    ///
+    /// Without verification, the checksums can still be checked once downloaded,
+    /// using the checks of [`DownloadedArtifact`](crate::ddi::DownloadedArtifact).
+    pub fn verification(self, verification: VerificationPolicy) -> Self {
+        let mut options = self;
+        options.verification = verification;
+        options
+    }
+
+    /// Download up to `max` artifacts at the same time, when downloading a whole
+    /// [`Update`] or [`Chunk`](crate::ddi::Chunk).
+    ///
+    /// Artifacts are still returned in order and the remaining transfers are
+    /// canceled on the first error. Default to 1, downloading artifacts one after another.
+    pub fn max_concurrent_downloads(self, max: usize) -> Self {
+        let mut options = self;
+        options.max_concurrent_downloads = max;
+        options
+    }
+
+    /// The maximum number of artifacts downloaded at the same time.
+    pub(crate) fn concurrency(&self) -> usize {
+        self.max_concurrent_downloads.max(1)
+    }
+
+    /// Decompress the artifacts while downloading them, according to `decompression`.
+    ///
+    /// Sizes and verified checksums are still checked on the compressed data sent by the server,
+    /// so [`DownloadedArtifact`](crate::ddi::DownloadedArtifact) checks cannot be used on
+    /// the decompressed files. With [`FileNaming::Filename`], the compression extension
+    /// is removed from the file names. Decompressed downloads cannot be resumed.
+    #[cfg(feature = "decompress")]
+    pub fn decompress(self, decompression: Decompression) -> Self {
+        let mut options = self;
+        options.decompression = Some(decompression);
+        options
+    }
+
+    /// Verify the signature of each artifact using `keys`, see [`TrustedKeys`].
+    ///
+    /// Downloads fail if an artifact is not signed by one of the trusted keys,
+    /// except for the signatures themselves. As checksums, signatures are verified
+    /// while downloading, on the data sent by the server.
+    #[cfg(feature = "signature")]
+    pub fn verify_signatures(self, keys: TrustedKeys) -> Self {
+        let mut options = self;
+        options.trusted_keys = Some(keys);
+        options
+    }
+
+    /// Return `true` if interrupted downloads can be resumed.
+    pub(crate) fn resumable(&self) -> bool {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "decompress")] {
+                self.resume && self.decompression.is_none()
+            } else {
+                self.resume
+            }
+        }
+    }
+
+    /// The name of the downloaded file of the artifact `file_name`.
+    pub(crate) fn local_file_name<'n>(&self, file_name: &'n str) -> &'n str {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "decompress")] {
+                match self.decompression {
+                    Some(_) => decompressed_file_name(file_name),
+                    None => file_name,
+                }
+            } else {
+                file_name
+            }
+        }
+    }
+
+    /// Call `callback` each time data have been downloaded.
+    pub fn progress<F>(self, callback: F) -> Self
+    where
+        F: Fn(&DownloadProgress) + Send + Sync + 'static,
+    {
+        let mut options = self;
+        options.progress = Some(Arc::new(callback));
+        options
+    }
+
+    /// Report the download progress to the server by sending [`Execution::Proceeding`](crate::ddi::Execution::Proceeding)
+    /// feedback, with the downloaded percentage as [`Progress`], at most once per `interval`.
+    ///
+    /// Only used when downloading a whole [`Update`]. The feedback is sent alongside the
+    /// download without slowing it down: it is neither retried nor queued in the feedback
+    /// journal, and failing to send it does not interrupt the download.
+    pub fn progress_feedback(self, interval: Duration) -> Self {
+        let mut options = self;
+        options.progress_feedback = Some(interval);
+        options
+    }
+}
+
+/// How the downloaded artifacts files are named, see [`DownloadOptions::file_naming`].
+///
+/// Whatever the naming, files are always downloaded in a sub-directory named after their chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FileNaming {
+    /// Use the file name of the artifact provided by the server.
+    #[default]
+    Filename,
+    /// Use the sha256 checksum of the artifact provided by the server, so files
+    /// are named independently of server supplied names.
+    Sha256,
+}
+
+/// Which checksums of the artifacts are verified, see [`DownloadOptions::verification`].
+///
+/// Checksums are computed while the artifact is downloaded, so the file is
+/// never published if one of them does not match.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VerificationPolicy {
+    /// Do not verify any checksum.
+    #[default]
+    None,
+    /// Verify all the checksums supported by the enabled `hash-*` features.
+    AllAvailable,
+    /// Only verify the given checksum.
+    #[cfg(feature = "hash-digest")]
+    Require(ChecksumType),
+}
+
+/// Which links provided by the server are used to download the artifacts, see
+/// [`ClientBuilder::link_policy`](crate::ddi::ClientBuilder::link_policy).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LinkPolicy {
+    /// Only use the HTTPS `download` links.
+    HttpsOnly,
+    /// Use the HTTPS `download` links, falling back to the HTTP `download-http` links
+    /// if the artifact is only provided over HTTP or the server cannot be reached over HTTPS.
+    #[default]
+    PreferHttps,
+    /// Only use the HTTP `download-http` links, such as on isolated networks.
+    HttpOnly,
+}
+
+/// Check that `name`, provided by the server, can safely be used as a file name:
+/// a single path component which cannot escape the download directory.
+pub(crate) fn check_file_name(name: &str) -> Result<&str, Error> {
+    let mut components = Path::new(name).components();
+    let single = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    );
+
+    if !single || name.contains(&['/', '\\', '\0'][..]) {
+        return Err(Error::InvalidFileName(name.to_string()));
+    }
+
+    Ok(name)
+}
+
+/// Progress of a download, see [`DownloadOptions::progress`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadProgress {
+    artifact: String,
+    downloaded: u64,
+    total: u64,
+    update_downloaded: u64,
+    update_total: u64,
+}
+
+impl DownloadProgress {
+    /// The file name of the artifact being downloaded.
+    pub fn artifact(&self) -> &str {
+        &self.artifact
+    }
+
+    /// The number of bytes of the artifact downloaded so far.
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
+    /// The size of the artifact, in bytes.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The number of bytes downloaded so far for all the artifacts being downloaded,
+    /// such as all the artifacts of an [`Update`].
+    pub fn update_downloaded(&self) -> u64 {
+        self.update_downloaded
+    }
+
+    /// The size of all the artifacts being downloaded, in bytes.
+    pub fn update_total(&self) -> u64 {
+        self.update_total
+    }
+}
+
+/// Write the data of an artifact to its file, decompressing them if needed.
+pub(crate) enum ArtifactWriter {
+    Plain(File),
+    #[cfg(feature = "decompress-gzip")]
+    Gzip(Box<async_compression::tokio::write::GzipDecoder<File>>),
+    #[cfg(feature = "decompress-xz")]
+    Xz(Box<async_compression::tokio::write::XzDecoder<File>>),
+    #[cfg(feature = "decompress-zstd")]
+    Zstd(Box<async_compression::tokio::write::ZstdDecoder<File>>),
+}
+
+impl ArtifactWriter {
+    /// Write the artifact `file_name`, whose data start with `data`, to `file`.
+    pub(crate) fn new(file: File, options: &DownloadOptions, file_name: &str, data: &[u8]) -> Self {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "decompress")] {
+                let compression = options
+                    .decompression
+                    .and_then(|d| d.compression(file_name, data));
+
+                match compression {
+                    None => ArtifactWriter::Plain(file),
+                    #[cfg(feature = "decompress-gzip")]
+                    Some(Compression::Gzip) => {
+                        ArtifactWriter::Gzip(Box::new(
+                            async_compression::tokio::write::GzipDecoder::new(file),
+                        ))
+                    }
+                    #[cfg(feature = "decompress-xz")]
+                    Some(Compression::Xz) => {
+                        ArtifactWriter::Xz(Box::new(
+                            async_compression::tokio::write::XzDecoder::new(file),
+                        ))
+                    }
+                    #[cfg(feature = "decompress-zstd")]
+                    Some(Compression::Zstd) => {
+                        ArtifactWriter::Zstd(Box::new(
+                            async_compression::tokio::write::ZstdDecoder::new(file),
+                        ))
+                    }
+                }
+            } else {
+                let _ = (options, file_name, data);
+                ArtifactWriter::Plain(file)
+            }
+        }
+    }
+
+    pub(crate) async fn write_all(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            ArtifactWriter::Plain(file) => file.write_all(data).await,
+            #[cfg(feature = "decompress-gzip")]
+            ArtifactWriter::Gzip(decoder) => decoder.write_all(data).await,
+            #[cfg(feature = "decompress-xz")]
+            ArtifactWriter::Xz(decoder) => decoder.write_all(data).await,
+            #[cfg(feature = "decompress-zstd")]
+            ArtifactWriter::Zstd(decoder) => decoder.write_all(data).await,
+        }
+    }
+
+    /// Write all the pending data, returning the file.
+    pub(crate) async fn finish(self) -> std::io::Result<File> {
+        match self {
+            ArtifactWriter::Plain(mut file) => {
+                file.flush().await?;
+                Ok(file)
+            }
+            #[cfg(feature = "decompress-gzip")]
+            ArtifactWriter::Gzip(mut decoder) => {
+                decoder.shutdown().await?;
+                Ok(decoder.into_inner())
+            }
+            #[cfg(feature = "decompress-xz")]
+            ArtifactWriter::Xz(mut decoder) => {
+                decoder.shutdown().await?;
+                Ok(decoder.into_inner())
+            }
+            #[cfg(feature = "decompress-zstd")]
+            ArtifactWriter::Zstd(mut decoder) => {
+                decoder.shutdown().await?;
+                Ok(decoder.into_inner())
+            }
+        }
+    }
+}
+
+/// Track the progress of a download, reporting it according to the [`DownloadOptions`].
+///
+/// The tracker is shared by all the artifacts being downloaded, which may be
+/// downloaded concurrently, each one reporting through its own [`ArtifactProgress`].
+pub(crate) struct ProgressTracker<'a> {
+    options: &'a DownloadOptions,
+    // progress feedback to send, if enabled, see `send_progress_feedback()`
+    feedback: Option<watch::Sender<Progress>>,
+    state: Mutex<TrackerState>,
+}
+
+struct TrackerState {
+    last_feedback: Option<Instant>,
+    completion_reported: bool,
+    update_downloaded: u64,
+    update_total: u64,
+}
+
+impl<'a> ProgressTracker<'a> {
+    /// Track the download of `total` bytes.
+    pub(crate) fn new(options: &'a DownloadOptions, total: u64) -> Self {
+        Self {
+            options,
+            feedback: None,
+            state: Mutex::new(TrackerState {
+                last_feedback: None,
+                completion_reported: false,
+                update_downloaded: 0,
+                update_total: total,
+            }),
+        }
+    }
+
+    /// Track the download of `total` bytes, also reporting the progress feedback
+    /// to the returned receiver, see [`send_progress_feedback`].
+    pub(crate) fn with_feedback(
+        options: &'a DownloadOptions,
+        total: u64,
+    ) -> (Self, watch::Receiver<Progress>) {
+        let (sender, receiver) = watch::channel(Progress::percentage(0, total));
+        let mut tracker = Self::new(options, total);
+        tracker.feedback = Some(sender);
+
+        (tracker, receiver)
+    }
+
+    /// Start downloading the artifact `name` of `size` bytes, from `offset` if resuming.
+    pub(crate) async fn start_artifact(
+        &self,
+        name: &str,
+        size: u64,
+        offset: u64,
+    ) -> ArtifactProgress<'_, 'a> {
+        let progress = ArtifactProgress {
+            tracker: self,
+            artifact: name.to_string(),
+            downloaded: offset,
+            total: size,
+        };
+        self.report(&progress, offset).await;
+
+        progress
+    }
+
+    /// `len` more bytes of the artifact of `progress` have been downloaded.
+    async fn report(&self, progress: &ArtifactProgress<'_, 'a>, len: u64) {
+        let mut state = self.state.lock().await;
+        state.update_downloaded += len;
+
+        if let Some(callback) = &self.options.progress {
+            callback(&DownloadProgress {
+                artifact: progress.artifact.clone(),
+                downloaded: progress.downloaded,
+                total: progress.total,
+                update_downloaded: state.update_downloaded,
+                update_total: state.update_total,
+            });
+        }
+
+        let (feedback, interval) = match (&self.feedback, self.options.progress_feedback) {
+            (Some(feedback), Some(interval)) => (feedback, interval),
+            _ => return,
+        };
+        let done = state.update_downloaded >= state.update_total;
+        let send = if done {
+            // always report the completion, but only once
+            !state.completion_reported
+        } else {
+            match state.last_feedback {
+                Some(last) => last.elapsed() >= interval,
+                None => true,
+            }
+        };
+        if !send {
+            return;
+        }
+        state.completion_reported = done;
+        state.last_feedback = Some(Instant::now());
+
+        // the download never waits for the feedback to be sent, which replaces
+        // any previous progress still waiting
+        let _ = feedback.send(Progress::percentage(
+            state.update_downloaded,
+            state.update_total,
+        ));
+    }
+}
+
+/// Send the progress feedback of `update` reported to `receiver`, until its
+/// [`ProgressTracker`] is dropped.
+///
+/// Only the latest progress is sent if the server is slower than the download.
+pub(crate) async fn send_progress_feedback(
+    update: &Update,
+    mut receiver: watch::Receiver<Progress>,
+) {
+    while receiver.changed().await.is_ok() {
+        let progress = *receiver.borrow_and_update();
+        // progress is informative, do not fail the download if it cannot be sent
+        let _ = update.send_download_progress(progress).await;
+    }
+}
+
+/// Progress of a single artifact, see [`ProgressTracker::start_artifact`].
+pub(crate) struct ArtifactProgress<'t, 'a> {
+    tracker: &'t ProgressTracker<'a>,
+    artifact: String,
+    downloaded: u64,
+    total: u64,
+}
+
+impl ArtifactProgress<'_, '_> {
+    /// `len` more bytes of the artifact have been downloaded.
+    pub(crate) async fn advance(&mut self, len: u64) {
+        self.downloaded += len;
+        self.tracker.report(self, len).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names() {
+        for name in &["test.txt", "..test", "a:b", "rootfs-1.0.ext4"] {
+            assert_eq!(check_file_name(name).unwrap(), *name);
+        }
+
+        for name in &[
+            "",
+            ".",
+            "..",
+            "../../etc/shadow",
+            "/etc/shadow",
+            "a/b",
+            "a\\b",
+            "a\0b",
+            "a/",
+        ] {
+            assert!(
+                matches!(check_file_name(name), Err(Error::InvalidFileName(n)) if n == *name),
+                "{:?} accepted",
+                name
+            );
+        }
+    }
+}
@@ -3,19 +3,29 @@
 
 // Structures used to send feedback on upgrades
 
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use serde::Serialize;
+use url::Url;
 
-use crate::ddi::common::{Execution, Finished};
+use crate::ddi::cancel_action::CancelAction;
+use crate::ddi::client::Error;
+use crate::ddi::common::{post_feedback, Execution, Finished};
+use crate::ddi::http::HttpClient;
 
 #[derive(Debug, Serialize)]
 pub(crate) struct Feedback<T: Serialize> {
     id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
     status: Status<T>,
 }
 #[derive(Debug, Serialize)]
 struct Status<T: Serialize> {
     execution: Execution,
     result: ResultT<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<i32>,
     details: Vec<String>,
 }
 
@@ -36,11 +46,153 @@ impl<T: Serialize> Feedback<T> {
     ) -> Self {
         Self {
             id: id.to_string(),
+            timestamp: None,
             status: Status {
                 execution,
                 details,
+                code: None,
                 result: ResultT { finished, progress },
             },
         }
     }
+
+    /// Return `true` if the action is closed once the server received this feedback.
+    pub(crate) fn closes_action(&self) -> bool {
+        self.status.execution.closes_action()
+    }
+}
+
+/// Builder for feedback about an action, such as using [`Update::feedback`](crate::ddi::Update::feedback),
+/// [`CancelAction::feedback`](crate::ddi::CancelAction::feedback) or
+/// [`ResumedAction::feedback`](crate::ddi::ResumedAction::feedback).
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn send(update: hawkbit::ddi::Update) -> Result<(), hawkbit::ddi::Error> {
+/// use hawkbit::ddi::{Execution, Finished, Progress};
+///
+/// update
+///     .feedback(Execution::Proceeding, Finished::None)
+///     .progress(Progress::new(2, 5))
+///     .code(12)
+///     .details(vec!["Installing"])
+///     .send()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FeedbackBuilder<'a> {
+    client: &'a HttpClient,
+    // the URL the feedback is sent to, errors are reported when sending
+    url: Result<Url, Error>,
+    // the cancel action whose id is fetched when sending, if not known yet
+    cancel_action: Option<&'a CancelAction>,
+    feedback: Feedback<Progress>,
+}
+
+impl<'a> FeedbackBuilder<'a> {
+    pub(crate) fn new(
+        client: &'a HttpClient,
+        url: Result<Url, Error>,
+        id: &str,
+        execution: Execution,
+        finished: Finished,
+    ) -> Self {
+        Self {
+            client,
+            url,
+            cancel_action: None,
+            feedback: Feedback::new(id, execution, finished, None, Vec::new()),
+        }
+    }
+
+    /// Feedback about `cancel_action`, whose id is fetched when sending.
+    pub(crate) fn cancel_action(
+        client: &'a HttpClient,
+        url: Result<Url, Error>,
+        cancel_action: &'a CancelAction,
+        execution: Execution,
+        finished: Finished,
+    ) -> Self {
+        let mut builder = Self::new(client, url, "", execution, finished);
+        builder.cancel_action = Some(cancel_action);
+        builder
+    }
+
+    /// Report the progress of the action.
+    pub fn progress(self, progress: Progress) -> Self {
+        let mut builder = self;
+        builder.feedback.status.result.progress = Some(progress);
+        builder
+    }
+
+    /// Add a numeric status code, defined by the device, providing more information
+    /// about the state of the action.
+    pub fn code(self, code: i32) -> Self {
+        let mut builder = self;
+        builder.feedback.status.code = Some(code);
+        builder
+    }
+
+    /// Set the time at which the reported state was reached, instead of letting
+    /// the server use the time it received the feedback.
+    pub fn timestamp(self, timestamp: SystemTime) -> Self {
+        let mut builder = self;
+        let millis = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_millis() as u64);
+        builder.feedback.timestamp = Some(millis);
+        builder
+    }
+
+    /// Set the list of details message information.
+    pub fn details(self, details: Vec<&str>) -> Self {
+        let mut builder = self;
+        builder.feedback.status.details = details.iter().map(|m| m.to_string()).collect();
+        builder
+    }
+
+    /// Send the feedback to the server.
+    ///
+    /// If the client uses a feedback journal, see [`ClientBuilder::feedback_journal`](crate::ddi::ClientBuilder::feedback_journal),
+    /// the feedback is queued if the server cannot be reached.
+    pub async fn send(self) -> Result<(), Error> {
+        let url = self.url?;
+        let mut feedback = self.feedback;
+        if let Some(cancel_action) = self.cancel_action {
+            feedback.id = cancel_action.id().await?;
+        }
+
+        post_feedback(self.client, url.as_str(), &feedback).await
+    }
+}
+
+/// Progress of an action, sent with feedback such as using
+/// [`Update::send_feedback_with_progress`](crate::ddi::Update::send_feedback_with_progress).
+///
+/// hawkBit displays it as `cnt` steps done out of `of`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Progress {
+    /// Number of steps done
+    pub cnt: u32,
+    /// Total number of steps
+    pub of: u32,
+}
+
+impl Progress {
+    /// Create a new progress of `cnt` steps done out of `of`.
+    pub fn new(cnt: u32, of: u32) -> Self {
+        Self { cnt, of }
+    }
+
+    /// Create the progress of `done` out of `total` as a percentage.
+    pub fn percentage(done: u64, total: u64) -> Self {
+        let cnt = (done.min(total) * 100)
+            .checked_div(total)
+            .map_or(100, |cnt| cnt as u32);
+
+        Self::new(cnt, 100)
+    }
 }
@@ -0,0 +1,153 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// HTTP client shared by all the DDI objects
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::header::{HeaderValue, AUTHORIZATION, RETRY_AFTER};
+use reqwest::{IntoUrl, Method, RequestBuilder, Response};
+use serde::Deserialize;
+
+use crate::ddi::client::{Error, ServerError};
+use crate::ddi::download::LinkPolicy;
+use crate::ddi::journal::FeedbackJournal;
+use crate::ddi::rate_limit::RateLimiter;
+use crate::ddi::retry::RetryPolicy;
+
+/// Wrapper around the `reqwest` client adding the DDI authentication
+/// to each request and retrying the failed ones.
+#[derive(Debug, Clone)]
+pub(crate) struct HttpClient {
+    client: reqwest::Client,
+    authorization: Option<HeaderValue>,
+    retry_policy: RetryPolicy,
+    journal: Option<Arc<FeedbackJournal>>,
+    rate_limiter: Arc<RateLimiter>,
+    link_policy: LinkPolicy,
+}
+
+impl HttpClient {
+    pub(crate) fn new(
+        client: reqwest::Client,
+        authorization: Option<HeaderValue>,
+        retry_policy: RetryPolicy,
+        journal: Option<FeedbackJournal>,
+        rate_limiter: RateLimiter,
+        link_policy: LinkPolicy,
+    ) -> Self {
+        Self {
+            client,
+            authorization,
+            retry_policy,
+            journal: journal.map(Arc::new),
+            rate_limiter: Arc::new(rate_limiter),
+            link_policy,
+        }
+    }
+
+    /// The journal storing the feedback which could not be sent, if any.
+    pub(crate) fn journal(&self) -> Option<&FeedbackJournal> {
+        self.journal.as_deref()
+    }
+
+    /// The limiter of the download bandwidth, shared by all the clones of the client.
+    pub(crate) fn rate_limiter(&self) -> &Arc<RateLimiter> {
+        &self.rate_limiter
+    }
+
+    /// Which links are used to download the artifacts.
+    pub(crate) fn link_policy(&self) -> LinkPolicy {
+        self.link_policy
+    }
+
+    pub(crate) fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    pub(crate) fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::POST, url)
+    }
+
+    pub(crate) fn put<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::PUT, url)
+    }
+
+    /// Send `request`, retrying it according to the retry policy of the client.
+    pub(crate) async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let mut attempt = 1;
+
+        loop {
+            // requests with a streaming body cannot be cloned and so retried
+            let retry = match request.try_clone() {
+                Some(retry) => retry,
+                None => return self.send_once(request).await,
+            };
+
+            match self.send_once(retry).await {
+                Err(e) => match self.retry_policy.delay(attempt, &e) {
+                    Some(delay) => {
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    None => return Err(e),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    /// Send `request`, without retrying it, and turn error replies from the server
+    /// into the matching [`Error`].
+    pub(crate) async fn send_once(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let response = request.send().await?;
+        let status = response.status();
+
+        if !status.is_client_error() && !status.is_server_error() {
+            return Ok(response);
+        }
+
+        // Only the delay in seconds form is supported, not the HTTP date one
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+            .map(Duration::from_secs);
+
+        // hawkBit describes the error in a JSON body, if any
+        let body = response
+            .bytes()
+            .await
+            .ok()
+            .and_then(|body| serde_json::from_slice::<ErrorBody>(&body).ok());
+        let (error_code, message) = match body {
+            Some(body) => (body.error_code, body.message),
+            None => (None, None),
+        };
+
+        Err(Error::from_server_error(ServerError::new(
+            status,
+            error_code,
+            message,
+            retry_after,
+        )))
+    }
+
+    fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
+        let request = self.client.request(method, url);
+
+        match &self.authorization {
+            Some(authorization) => request.header(AUTHORIZATION, authorization.clone()),
+            None => request,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    #[serde(rename = "errorCode")]
+    error_code: Option<String>,
+    message: Option<String>,
+}
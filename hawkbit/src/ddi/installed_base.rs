@@ -0,0 +1,95 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// Last action installed on the device, as seen by the server
+
+use std::path::Path;
+
+use crate::ddi::client::Error;
+use crate::ddi::deployment_base::{
+    download_chunks, ActionHistory, Chunk, DownloadedArtifact, Reply, Type,
+};
+use crate::ddi::download::DownloadOptions;
+use crate::ddi::http::HttpClient;
+
+/// The last action installed on the device, whose details have not been retrieved yet.
+///
+/// Call [`InstalledPreFetch::fetch()`] to retrieve the details from server.
+#[derive(Debug)]
+pub struct InstalledPreFetch {
+    client: HttpClient,
+    url: String,
+}
+
+impl InstalledPreFetch {
+    pub(crate) fn new(client: HttpClient, url: String) -> Self {
+        Self { client, url }
+    }
+
+    /// Retrieve details about the installed action.
+    pub async fn fetch(self) -> Result<Installed, Error> {
+        let reply = self.client.send(self.client.get(&self.url)).await?;
+
+        let reply = reply.json::<Reply>().await?;
+        Ok(Installed {
+            client: self.client,
+            info: reply,
+        })
+    }
+}
+
+/// The software installed on the device by its last action, according to the server.
+///
+/// This can be compared with the versions actually installed on the device, and
+/// its artifacts downloaded again if needed, such as after a factory reset.
+#[derive(Debug)]
+pub struct Installed {
+    client: HttpClient,
+    info: Reply,
+}
+
+impl Installed {
+    /// The id of the installed action.
+    pub fn id(&self) -> &str {
+        &self.info.id
+    }
+
+    /// Handling for the download part of the provisioning process.
+    pub fn download_type(&self) -> Type {
+        self.info.deployment.download
+    }
+
+    /// Handling for the update part of the provisioning process.
+    pub fn update_type(&self) -> Type {
+        self.info.deployment.update
+    }
+
+    /// The history of the action, if provided by the server.
+    pub fn action_history(&self) -> Option<&ActionHistory> {
+        self.info.action_history.as_ref()
+    }
+
+    /// An iterator on all the installed software chunks.
+    pub fn chunks(&self) -> impl Iterator<Item = Chunk<'_>> {
+        self.info.deployment.chunks(&self.client)
+    }
+
+    /// Download all software chunks to the directory defined in `dir`.
+    pub async fn download(&self, dir: &Path) -> Result<Vec<DownloadedArtifact>, Error> {
+        self.download_with_options(dir, &DownloadOptions::default())
+            .await
+    }
+
+    /// Download all software chunks to the directory defined in `dir`, using `options`.
+    ///
+    /// As the action is already installed, no progress feedback is sent to the server,
+    /// see [`DownloadOptions::progress_feedback`].
+    pub async fn download_with_options(
+        &self,
+        dir: &Path,
+        options: &DownloadOptions,
+    ) -> Result<Vec<DownloadedArtifact>, Error> {
+        let chunks: Vec<Chunk> = self.chunks().collect();
+        download_chunks(&chunks, dir, options, None).await
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// On-disk queue of the feedback which could not be sent to the server
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::ddi::client::Error;
+use crate::ddi::common::write_file_atomic;
+use crate::ddi::http::HttpClient;
+use crate::ddi::retry::RetryPolicy;
+
+/// Feedback waiting to be sent to the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    /// URL the feedback is sent to, identifying the action
+    url: String,
+    /// `true` if the feedback closes the action
+    closing: bool,
+    feedback: serde_json::Value,
+}
+
+impl Entry {
+    pub(crate) fn new(url: &str, closing: bool, feedback: serde_json::Value) -> Self {
+        Self {
+            url: url.to_string(),
+            closing,
+            feedback,
+        }
+    }
+}
+
+/// Journal storing the feedback which could not be sent because the server
+/// was unreachable, so they are sent later, in order, once it is back.
+#[derive(Debug)]
+pub(crate) struct FeedbackJournal {
+    path: PathBuf,
+    entries: Mutex<Vec<Entry>>,
+    // held during the whole replay so entries are sent once and in order
+    replay: Mutex<()>,
+}
+
+impl FeedbackJournal {
+    /// Open the journal stored in `path`, creating it if needed.
+    pub(crate) fn open(path: &Path) -> Result<Self, Error> {
+        let entries = match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(Error::InvalidJournal)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries: Mutex::new(entries),
+            replay: Mutex::new(()),
+        })
+    }
+
+    /// Send `entry` to the server, after any previously queued feedback.
+    /// The entry is kept in the journal if the server cannot be reached.
+    pub(crate) async fn send(&self, client: &HttpClient, entry: Entry) -> Result<(), Error> {
+        self.queue(entry.clone()).await?;
+
+        match self.replay_entries(client, Some(&entry)).await {
+            // server is still unreachable, the entry will be sent later
+            Err(e) if RetryPolicy::is_transient(&e) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Send all the queued feedback, stopping at the first one which cannot be delivered.
+    pub(crate) async fn replay(&self, client: &HttpClient) -> Result<(), Error> {
+        match self.replay_entries(client, None).await {
+            Err(e) if RetryPolicy::is_transient(&e) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Number of feedback waiting to be sent.
+    pub(crate) async fn len(&self) -> usize {
+        self.entries.lock().await.len()
+    }
+
+    /// Send the queued entries, removing the ones which have been sent or have been
+    /// rejected by the server. Return the error which stopped the replay, if any,
+    /// or the error rejecting `own` if it was in the journal.
+    ///
+    /// The entries are not locked while sending, so feedback can still be queued
+    /// during a slow replay.
+    async fn replay_entries(&self, client: &HttpClient, own: Option<&Entry>) -> Result<(), Error> {
+        let _replay = self.replay.lock().await;
+        let snapshot = self.entries.lock().await.clone();
+        let mut done = 0;
+        let mut result = Ok(());
+
+        for entry in snapshot.iter() {
+            match Self::send_entry(client, entry).await {
+                Ok(()) => done += 1,
+                // the server is still unreachable or the device is no longer
+                // authorized, try again later
+                Err(e) if RetryPolicy::is_transient(&e) || matches!(e, Error::Unauthorized(_)) => {
+                    result = Err(e);
+                    break;
+                }
+                // the feedback is no longer relevant, such as when the action
+                // has been closed or canceled meanwhile
+                Err(e) if Some(entry) == own => {
+                    result = Err(e);
+                    done += 1;
+                }
+                Err(e) => {
+                    log::warn!(
+                        "Dropping feedback to {} rejected by the server: {}",
+                        entry.url,
+                        e
+                    );
+                    done += 1;
+                }
+            }
+        }
+
+        if done > 0 {
+            let mut entries = self.entries.lock().await;
+            // entries may have been collapsed by newly queued feedback meanwhile
+            let sent = entries
+                .iter()
+                .zip(&snapshot[..done])
+                .take_while(|(entry, sent)| entry == sent)
+                .count();
+            if sent > 0 {
+                entries.drain(..sent);
+                self.save(&entries).await?;
+            }
+        }
+
+        result
+    }
+
+    /// Queue `entry` to be sent later.
+    async fn queue(&self, entry: Entry) -> Result<(), Error> {
+        let mut entries = self.entries.lock().await;
+        Self::push(&mut entries, entry);
+        self.save(&entries).await
+    }
+
+    async fn send_entry(client: &HttpClient, entry: &Entry) -> Result<(), Error> {
+        client
+            .send(client.post(&entry.url).json(&entry.feedback))
+            .await?;
+        Ok(())
+    }
+
+    /// Queue `entry`, collapsing it with the feedback already queued for the same action.
+    fn push(entries: &mut Vec<Entry>, entry: Entry) {
+        let mut previous = entries.iter().filter(|e| e.url == entry.url);
+
+        // the action has already been closed, or the same feedback is already queued
+        if previous.any(|e| e.closing || *e == entry) {
+            return;
+        }
+
+        // only the final result matters once the action is closed
+        if entry.closing {
+            entries.retain(|e| e.url != entry.url);
+        }
+
+        entries.push(entry);
+    }
+
+    async fn save(&self, entries: &[Entry]) -> Result<(), Error> {
+        let data = serde_json::to_vec(entries).map_err(Error::InvalidJournal)?;
+        write_file_atomic(&self.path, &data).await
+    }
+}
@@ -5,14 +5,16 @@
 
 use std::time::Duration;
 
-use reqwest::Client;
 use serde::Deserialize;
 
 use crate::ddi::cancel_action::CancelAction;
 use crate::ddi::client::Error;
 use crate::ddi::common::Link;
 use crate::ddi::config_data::ConfigRequest;
+use crate::ddi::confirmation::ConfirmationRequest;
 use crate::ddi::deployment_base::UpdatePreFetch;
+use crate::ddi::http::HttpClient;
+use crate::ddi::installed_base::InstalledPreFetch;
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct ReplyInternal {
@@ -36,17 +38,21 @@ pub struct Links {
     deployment_base: Option<Link>,
     #[serde(rename = "cancelAction")]
     cancel_action: Option<Link>,
+    #[serde(rename = "confirmationBase")]
+    confirmation_base: Option<Link>,
+    #[serde(rename = "installedBase")]
+    installed_base: Option<Link>,
 }
 
 /// Polling reply from the server
 #[derive(Debug)]
 pub struct Reply {
     reply: ReplyInternal,
-    client: Client,
+    client: HttpClient,
 }
 
 impl Reply {
-    pub(crate) fn new(reply: ReplyInternal, client: Client) -> Self {
+    pub(crate) fn new(reply: ReplyInternal, client: HttpClient) -> Self {
         Self { reply, client }
     }
 
@@ -77,6 +83,28 @@ impl Reply {
         }
     }
 
+    /// Returns pending action waiting to be confirmed by the device, if any.
+    pub fn confirmation_request(&self) -> Option<ConfirmationRequest> {
+        match &self.reply.links {
+            Some(links) => links
+                .confirmation_base
+                .as_ref()
+                .map(|l| ConfirmationRequest::new(self.client.clone(), l.to_string())),
+            None => None,
+        }
+    }
+
+    /// Returns the last action installed on the device according to the server, if any.
+    pub fn installed(&self) -> Option<InstalledPreFetch> {
+        match &self.reply.links {
+            Some(links) => links
+                .installed_base
+                .as_ref()
+                .map(|l| InstalledPreFetch::new(self.client.clone(), l.to_string())),
+            None => None,
+        }
+    }
+
     /// Returns pending cancel action, if any.
     pub fn cancel_action(&self) -> Option<CancelAction> {
         match &self.reply.links {
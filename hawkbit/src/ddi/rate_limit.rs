@@ -0,0 +1,125 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// Bandwidth limit of the artifact downloads
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Token bucket limiting the download bandwidth of all the artifacts downloaded
+/// by a client, see [`ClientBuilder::download_rate_limit`](crate::ddi::ClientBuilder::download_rate_limit).
+///
+/// The bucket holds up to one second worth of data, allowing short bursts.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    // bytes per second, no limit if `None`
+    rate: Option<u64>,
+    // bytes which can be received without waiting, negative if in debt
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: Option<u64>) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                rate,
+                tokens: rate.unwrap_or(0) as f64,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// The current limit, in bytes per second.
+    pub(crate) fn rate(&self) -> Option<u64> {
+        self.bucket.lock().unwrap().rate
+    }
+
+    /// Change the limit, in bytes per second, applying to the downloads in progress as well.
+    pub(crate) fn set_rate(&self, rate: Option<u64>) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.rate = rate;
+        bucket.tokens = rate.unwrap_or(0) as f64;
+        bucket.last = Instant::now();
+    }
+
+    /// Account for `len` received bytes, returning how long to wait before
+    /// receiving more data to stay within the limit.
+    pub(crate) fn consume(&self, len: usize) -> Option<Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let rate = match bucket.rate {
+            Some(rate) if rate > 0 => rate as f64,
+            _ => return None,
+        };
+
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.last).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refill).min(rate) - len as f64;
+        bucket.last = now;
+
+        if bucket.tokens < 0.0 {
+            Some(Duration::from_secs_f64(-bucket.tokens / rate))
+        } else {
+            None
+        }
+    }
+
+    /// Account for `len` received bytes, waiting as long as needed to stay within the limit.
+    pub(crate) async fn throttle(&self, len: usize) {
+        if let Some(delay) = self.consume(len) {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        let limiter = RateLimiter::new(None);
+        assert_eq!(limiter.consume(1_000_000), None);
+
+        limiter.set_rate(Some(1000));
+        assert_eq!(limiter.rate(), Some(1000));
+        // a one second burst is allowed
+        assert_eq!(limiter.consume(1000), None);
+        // then the data exceeding the rate have to be waited for
+        let delay = limiter.consume(500).unwrap();
+        assert!(delay > Duration::from_millis(400) && delay <= Duration::from_millis(500));
+
+        limiter.set_rate(None);
+        assert_eq!(limiter.consume(1_000_000), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn throttle() {
+        // 11 bytes at 5 bytes per second, with a burst of 5 bytes
+        let limiter = RateLimiter::new(Some(5));
+        let start = Instant::now();
+        limiter.throttle(5).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        limiter.throttle(6).await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(1200) && elapsed < Duration::from_millis(1210));
+
+        // the tokens are refilled over time
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let start = Instant::now();
+        limiter.throttle(5).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // the limit can be removed at runtime
+        limiter.set_rate(None);
+        limiter.throttle(1_000_000).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+}
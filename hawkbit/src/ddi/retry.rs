@@ -0,0 +1,217 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// Policy used to retry failed requests
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::ddi::client::Error;
+
+/// Policy defining how failed requests to the server are retried.
+///
+/// Failed requests are retried with an exponential backoff: the first retry happens after
+/// [`RetryPolicy::initial_backoff`] and each next one waits [`RetryPolicy::multiplier`]
+/// times longer, up to [`RetryPolicy::max_backoff`].
+/// If the server asks the controller to wait using the `Retry-After` header,
+/// such as with `429 Too Many Requests` or `503 Service Unavailable` replies,
+/// this delay is used instead, still up to [`RetryPolicy::max_backoff`].
+///
+/// By default requests are not retried.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use hawkbit::ddi::{ClientBuilder, RetryPolicy};
+///
+/// let policy = RetryPolicy::default()
+///     .max_attempts(5)
+///     .initial_backoff(Duration::from_secs(2));
+///
+/// let client = ClientBuilder::new("http://my-server.com:8080", "DEFAULT", "my-device", "my-token")
+///     .retry_policy(policy)
+///     .build()
+///     .expect("DDI creation failed");
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: bool,
+    retry_on: Arc<dyn Fn(&Error) -> bool + Send + Sync>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: true,
+            retry_on: Arc::new(RetryPolicy::is_transient),
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("multiplier", &self.multiplier)
+            .field("jitter", &self.jitter)
+            .finish()
+    }
+}
+
+impl RetryPolicy {
+    /// Set the maximum number of times a request is sent, including the first attempt.
+    /// Default to `1`, meaning requests are not retried.
+    pub fn max_attempts(self, max_attempts: u32) -> Self {
+        let mut policy = self;
+        policy.max_attempts = max_attempts.max(1);
+        policy
+    }
+
+    /// Set the delay before the first retry, default to 1 second.
+    pub fn initial_backoff(self, backoff: Duration) -> Self {
+        let mut policy = self;
+        policy.initial_backoff = backoff;
+        policy
+    }
+
+    /// Set the maximum delay between two attempts, default to 60 seconds.
+    pub fn max_backoff(self, backoff: Duration) -> Self {
+        let mut policy = self;
+        policy.max_backoff = backoff;
+        policy
+    }
+
+    /// Set the factor applied to the delay after each failed attempt, default to `2`.
+    pub fn multiplier(self, multiplier: f64) -> Self {
+        let mut policy = self;
+        policy.multiplier = multiplier.max(1.0);
+        policy
+    }
+
+    /// Randomize the delays between half and all of their value, so
+    /// controllers failing at the same time do not retry all at once.
+    /// Enabled by default.
+    pub fn jitter(self, jitter: bool) -> Self {
+        let mut policy = self;
+        policy.jitter = jitter;
+        policy
+    }
+
+    /// Set the predicate deciding if a failed request should be retried,
+    /// default to [`RetryPolicy::is_transient`].
+    pub fn retry_on<F>(self, retry_on: F) -> Self
+    where
+        F: Fn(&Error) -> bool + Send + Sync + 'static,
+    {
+        let mut policy = self;
+        policy.retry_on = Arc::new(retry_on);
+        policy
+    }
+
+    /// Return `true` if `error` is likely to be temporary: network failures,
+    /// timeouts, rate limiting and server failures.
+    pub fn is_transient(error: &Error) -> bool {
+        match error {
+            Error::ReqwestError(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            Error::TooManyRequests(_) | Error::ServerFailure(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Return the delay to wait before the attempt following the failed `attempt`,
+    /// or `None` if the request should not be retried.
+    pub(crate) fn delay(&self, attempt: u32, error: &Error) -> Option<Duration> {
+        if attempt >= self.max_attempts || !(self.retry_on)(error) {
+            return None;
+        }
+
+        if let Some(retry_after) = error.retry_after() {
+            return Some(retry_after.min(self.max_backoff));
+        }
+
+        let backoff = self.initial_backoff.as_secs_f64()
+            * self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        let mut backoff = backoff.min(self.max_backoff.as_secs_f64());
+        if self.jitter {
+            backoff *= rand::thread_rng().gen_range(0.5..=1.0);
+        }
+
+        Some(Duration::from_secs_f64(backoff))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server_failure() -> Error {
+        server_failure_retry_after(None)
+    }
+
+    fn server_failure_retry_after(retry_after: Option<Duration>) -> Error {
+        Error::from_server_error(crate::ddi::client::ServerError::new(
+            reqwest::StatusCode::SERVICE_UNAVAILABLE,
+            None,
+            None,
+            retry_after,
+        ))
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy::default()
+            .max_attempts(5)
+            .initial_backoff(Duration::from_secs(1))
+            .max_backoff(Duration::from_secs(5))
+            .jitter(false);
+        let error = server_failure();
+
+        assert_eq!(policy.delay(1, &error), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(2, &error), Some(Duration::from_secs(2)));
+        assert_eq!(policy.delay(3, &error), Some(Duration::from_secs(4)));
+        assert_eq!(policy.delay(4, &error), Some(Duration::from_secs(5)));
+        assert_eq!(policy.delay(5, &error), None);
+
+        let policy = policy.jitter(true);
+        for attempt in 1..5 {
+            let delay = policy.delay(attempt, &error).unwrap();
+            assert!(delay >= Duration::from_millis(500));
+            assert!(delay <= Duration::from_secs(5));
+        }
+
+        let policy = policy.retry_on(|_| false);
+        assert_eq!(policy.delay(1, &error), None);
+
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1, &error), None);
+    }
+
+    #[test]
+    fn retry_after() {
+        let policy = RetryPolicy::default()
+            .max_attempts(5)
+            .max_backoff(Duration::from_secs(60));
+
+        // the delay requested by the server is used as is
+        let error = server_failure_retry_after(Some(Duration::from_secs(10)));
+        assert_eq!(policy.delay(1, &error), Some(Duration::from_secs(10)));
+
+        // but cannot exceed the maximum backoff
+        let error = server_failure_retry_after(Some(Duration::from_secs(3 * 3600)));
+        assert_eq!(policy.delay(1, &error), Some(Duration::from_secs(60)));
+    }
+}
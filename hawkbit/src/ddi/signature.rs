@@ -0,0 +1,186 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// Verification of the artifacts signatures
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures::channel::mpsc;
+use futures::{Future, SinkExt, Stream, StreamExt};
+use minisign_verify::{PublicKey, Signature, StreamVerifier};
+
+use crate::ddi::client::Error;
+
+/// Public keys trusted to sign the artifacts, see
+/// [`DownloadOptions::verify_signatures`](crate::ddi::DownloadOptions::verify_signatures).
+///
+/// Artifacts are signed using [minisign](https://jedisct1.github.io/minisign/).
+/// The signature of an artifact is provided by the server either as a sibling artifact
+/// of the same chunk, named after the artifact with the `.sig` extension, or as a chunk
+/// metadata using the same name as key.
+///
+/// # Examples
+///
+/// ```
+/// use hawkbit::ddi::{DownloadOptions, TrustedKeys};
+///
+/// let keys = TrustedKeys::new()
+///     .add_key("RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3")
+///     .expect("invalid key");
+/// let options = DownloadOptions::default().verify_signatures(keys);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TrustedKeys {
+    keys: Vec<PublicKey>,
+}
+
+impl TrustedKeys {
+    /// Create an empty set of keys.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust `key`, either base64 encoded or the content of a minisign public key file.
+    pub fn add_key(self, key: &str) -> Result<Self, Error> {
+        let key = key.trim();
+        let key = if key.contains('\n') {
+            PublicKey::decode(key)
+        } else {
+            PublicKey::from_base64(key)
+        }
+        .map_err(Error::InvalidSignature)?;
+
+        let mut keys = self;
+        keys.keys.push(key);
+        Ok(keys)
+    }
+
+    /// Start verifying data signed with `signature`, using the matching trusted key.
+    fn verifier<'a>(&'a self, signature: &'a Signature) -> Result<StreamVerifier<'a>, Error> {
+        for key in self.keys.iter() {
+            match key.verify_stream(signature) {
+                Err(minisign_verify::Error::UnexpectedKeyId) => continue,
+                result => return result.map_err(Error::InvalidSignature),
+            }
+        }
+
+        Err(Error::InvalidSignature(
+            minisign_verify::Error::UnexpectedKeyId,
+        ))
+    }
+}
+
+/// A verified signature of an artifact, see
+/// [`DownloadedArtifact::signature`](crate::ddi::DownloadedArtifact::signature).
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifiedSignature {
+    trusted_comment: String,
+}
+
+impl VerifiedSignature {
+    /// The trusted comment of the signature, such as its timestamp and the name of
+    /// the signed file, which is authenticated along with the artifact.
+    pub fn trusted_comment(&self) -> &str {
+        &self.trusted_comment
+    }
+}
+
+/// Parse the content of a minisign signature file.
+pub(crate) fn decode_signature(data: &str) -> Result<Signature, Error> {
+    Signature::decode(data).map_err(Error::InvalidSignature)
+}
+
+/// Verify the signature of data while they are downloaded.
+pub(crate) struct SignatureCheck<'a> {
+    verifier: StreamVerifier<'a>,
+    signature: &'a Signature,
+}
+
+impl<'a> SignatureCheck<'a> {
+    pub(crate) fn new(keys: &'a TrustedKeys, signature: &'a Signature) -> Result<Self, Error> {
+        Ok(Self {
+            verifier: keys.verifier(signature)?,
+            signature,
+        })
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.verifier.update(data);
+    }
+
+    pub(crate) fn finalize(mut self) -> Result<VerifiedSignature, Error> {
+        self.verifier.finalize().map_err(Error::InvalidSignature)?;
+
+        Ok(VerifiedSignature {
+            trusted_comment: self.signature.trusted_comment().to_string(),
+        })
+    }
+}
+
+/// Verify the signature of `stream`, raising an error at the end of the stream if invalid.
+pub(crate) fn verify_stream<S>(
+    stream: S,
+    keys: TrustedKeys,
+    signature: Signature,
+) -> impl Stream<Item = Result<Bytes, Error>>
+where
+    S: Stream<Item = Result<Bytes, Error>> + Send + 'static,
+{
+    // the verifier borrows the key and the signature, so the verification runs in a
+    // future owning them, which forwards the data once they have been hashed
+    let (mut tx, rx) = mpsc::channel(0);
+    let task = async move {
+        let mut check = match SignatureCheck::new(&keys, &signature) {
+            Ok(check) => check,
+            Err(e) => {
+                let _ = tx.send(Err(e)).await;
+                return;
+            }
+        };
+
+        let mut stream = Box::pin(stream);
+        while let Some(data) = stream.next().await {
+            let failed = match &data {
+                Ok(data) => {
+                    check.update(data);
+                    false
+                }
+                Err(_) => true,
+            };
+            if tx.send(data).await.is_err() || failed {
+                return;
+            }
+        }
+
+        if let Err(e) = check.finalize() {
+            let _ = tx.send(Err(e)).await;
+        }
+    };
+
+    SignedStream {
+        task: Some(Box::pin(task)),
+        rx,
+    }
+}
+
+struct SignedStream {
+    // `None` once the whole stream has been verified
+    task: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    rx: mpsc::Receiver<Result<Bytes, Error>>,
+}
+
+impl Stream for SignedStream {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(task) = &mut self.task {
+            if task.as_mut().poll(cx).is_ready() {
+                self.task = None;
+            }
+        }
+
+        self.rx.poll_next_unpin(cx)
+    }
+}
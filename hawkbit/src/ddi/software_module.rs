@@ -0,0 +1,36 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// Artifacts of a software module, retrieved outside of any action
+
+use crate::ddi::deployment_base::{Artifact, ArtifactInternal, ChunkInternal};
+use crate::ddi::http::HttpClient;
+
+/// The artifacts of a software module, see
+/// [`Client::software_module_artifacts`](crate::ddi::Client::software_module_artifacts).
+#[derive(Debug)]
+pub struct SoftwareModule {
+    client: HttpClient,
+    id: u64,
+    chunk: ChunkInternal,
+}
+
+impl SoftwareModule {
+    pub(crate) fn new(client: HttpClient, id: u64, artifacts: Vec<ArtifactInternal>) -> Self {
+        Self {
+            client,
+            id,
+            chunk: ChunkInternal::software_module(id, artifacts),
+        }
+    }
+
+    /// The id of the software module.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// An iterator on all the artifacts of the software module.
+    pub fn artifacts(&self) -> impl Iterator<Item = Artifact<'_>> {
+        self.chunk.artifacts(&self.client)
+    }
+}
@@ -3,17 +3,24 @@
 
 use std::fs::File;
 use std::io::prelude::*;
-use std::{path::PathBuf, time::Duration};
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
 
+use async_trait::async_trait;
 use bytes::Bytes;
 use futures::prelude::*;
-use hawkbit::ddi::{Client, Error, Execution, Finished, MaintenanceWindow, Mode, Type};
+use hawkbit::ddi::{
+    ActionState, Agent, Auth, Client, ClientBuilder, DownloadOptions, DownloadedArtifact, Error,
+    Execution, FileNaming, Finished, MaintenanceWindow, Mode, Outcome, Phase, RetryPolicy, Type,
+    Update, UpdateHandler,
+};
 use serde::Serialize;
 use serde_json::json;
 use tempdir::TempDir;
+use tokio_util::sync::CancellationToken;
 
 use hawkbit_mock::ddi::{
-    ChunkProtocol, Deployment, DeploymentBuilder, Server, ServerBuilder, Target,
+    ChunkProtocol, Deployment, DeploymentBuilder, DeploymentFeedback, Server, ServerBuilder, Target,
 };
 
 fn init() {
@@ -53,6 +60,379 @@ async fn poll() {
     }
 }
 
+#[tokio::test]
+async fn custom_tenant() {
+    init();
+
+    let server = ServerBuilder::default().tenant("my-tenant").build();
+    let (client, target) = add_target(&server, "Target1");
+    target.request_confirmation(get_deployment(true));
+    target.set_installed(get_deployment(true));
+
+    let reply = client.poll().await.expect("poll failed");
+    let request = reply
+        .confirmation_request()
+        .expect("missing confirmation request");
+    let confirmation = request.fetch().await.expect("failed to fetch confirmation");
+    assert_eq!(confirmation.id(), "10");
+    assert_eq!(target.confirmation_hits(), 1);
+
+    let installed = reply.installed().expect("missing installed action");
+    let installed = installed.fetch().await.expect("failed to fetch installed");
+    assert_eq!(installed.id(), "10");
+    assert_eq!(target.installed_hits(), 1);
+
+    target.add_software_modules(
+        DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
+            .chunk(
+                ChunkProtocol::HTTP,
+                "app",
+                "1.0",
+                "some-chunk",
+                vec![test_artifact(artifact_path())],
+            )
+            .chunk_id("some-chunk", 42)
+            .build(),
+    );
+    let module = client
+        .software_module_artifacts(42)
+        .await
+        .expect("failed to fetch software module");
+    assert_eq!(module.artifacts().count(), 1);
+}
+
+#[tokio::test]
+async fn client_builder() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let target = server.add_target("Target1");
+
+    let client = ClientBuilder::new(
+        &server.base_url(),
+        &server.tenant,
+        &target.name,
+        &target.key,
+    )
+    .connect_timeout(Duration::from_secs(5))
+    .timeout(Duration::from_secs(30))
+    .user_agent("hawkbit-rs-test")
+    .build()
+    .expect("DDI creation failed");
+
+    client.poll().await.expect("poll failed");
+    assert_eq!(target.poll_hits(), 1);
+
+    // Use a custom reqwest client
+    let client = ClientBuilder::new(
+        &server.base_url(),
+        &server.tenant,
+        &target.name,
+        &target.key,
+    )
+    .client(reqwest::Client::new())
+    .build()
+    .expect("DDI creation failed");
+
+    client.poll().await.expect("poll failed");
+    assert_eq!(target.poll_hits(), 2);
+}
+
+fn tls_data(name: &str) -> Vec<u8> {
+    let mut path = PathBuf::new();
+    path.push("tests");
+    path.push("data");
+    path.push("tls");
+    path.push(name);
+
+    std::fs::read(path).expect("failed to read TLS data")
+}
+
+fn tls_server() -> Server {
+    ServerBuilder::default()
+        .tls(
+            &tls_data("server.pem"),
+            &tls_data("server.key"),
+            Some(&tls_data("ca.pem")),
+        )
+        .build()
+}
+
+fn tls_client_builder(server: &Server, target: &Target) -> ClientBuilder {
+    let ca = reqwest::Certificate::from_pem(&tls_data("ca.pem")).unwrap();
+
+    ClientBuilder::new(
+        &server.base_url(),
+        &server.tenant,
+        &target.name,
+        &target.key,
+    )
+    .add_root_certificate(ca)
+}
+
+#[tokio::test]
+async fn client_certificate() {
+    init();
+
+    let server = tls_server();
+    let target = server.add_target("Target1");
+
+    // PEM identity
+    let identity =
+        reqwest::Identity::from_pkcs8_pem(&tls_data("client.pem"), &tls_data("client.key"))
+            .unwrap();
+    let client = tls_client_builder(&server, &target)
+        .identity(identity)
+        .build()
+        .expect("DDI creation failed");
+
+    let reply = client.poll().await.expect("poll failed");
+    assert_eq!(reply.polling_sleep().unwrap(), Duration::from_secs(60));
+    assert_eq!(target.poll_hits(), 1);
+
+    // PKCS#12 identity
+    let identity = reqwest::Identity::from_pkcs12_der(&tls_data("client.p12"), "hawkbit").unwrap();
+    let client = tls_client_builder(&server, &target)
+        .identity(identity)
+        .build()
+        .expect("DDI creation failed");
+
+    client.poll().await.expect("poll failed");
+    assert_eq!(target.poll_hits(), 2);
+
+    // No client certificate, the server rejects the connection
+    let client = tls_client_builder(&server, &target)
+        .build()
+        .expect("DDI creation failed");
+
+    assert!(client.poll().await.is_err());
+    assert_eq!(target.poll_hits(), 2);
+}
+
+#[tokio::test]
+async fn auth() {
+    init();
+
+    let server = ServerBuilder::default().build();
+
+    for (i, auth) in [Auth::GatewayToken("Gateway".to_string()), Auth::None]
+        .iter()
+        .enumerate()
+    {
+        let target = server.add_target_with_auth(&format!("Target{}", i), auth.clone());
+        let client = ClientBuilder::new(&server.base_url(), &server.tenant, &target.name, "")
+            .auth(target.auth.clone())
+            .build()
+            .expect("DDI creation failed");
+
+        client.poll().await.expect("poll failed");
+        assert_eq!(target.poll_hits(), 1);
+    }
+
+    // Server requires a gateway token but client sends its target token
+    let target = server.add_target_with_auth("Target3", Auth::GatewayToken("Gateway".to_string()));
+    let client = Client::new(
+        &server.base_url(),
+        &server.tenant,
+        &target.name,
+        &target.key,
+    )
+    .expect("DDI creation failed");
+
+    assert!(client.poll().await.is_err());
+    assert_eq!(target.poll_hits(), 0);
+}
+
+#[tokio::test]
+async fn client_certificate_without_token() {
+    init();
+
+    let server = tls_server();
+    let target = server.add_target_with_auth("Target1", Auth::None);
+
+    let identity =
+        reqwest::Identity::from_pkcs8_pem(&tls_data("client.pem"), &tls_data("client.key"))
+            .unwrap();
+    let ca = reqwest::Certificate::from_pem(&tls_data("ca.pem")).unwrap();
+    let client =
+        ClientBuilder::with_identity(&server.base_url(), &server.tenant, &target.name, identity)
+            .add_root_certificate(ca)
+            .build()
+            .expect("DDI creation failed");
+
+    client.poll().await.expect("poll failed");
+    assert_eq!(target.poll_hits(), 1);
+}
+
+#[tokio::test]
+async fn server_errors() {
+    use assert_matches::assert_matches;
+
+    init();
+
+    let server = ServerBuilder::default().build();
+    let (client, target) = add_target(&server, "Target1");
+
+    // Unknown controller
+    let unknown = Client::new(&server.base_url(), &server.tenant, "Unknown", &target.key)
+        .expect("DDI creation failed");
+    let err = unknown.poll().await.unwrap_err();
+    assert_matches!(err, Error::NotFound(_));
+    assert_eq!(err.status(), Some(reqwest::StatusCode::NOT_FOUND));
+
+    // Error with details in the body
+    let mut mock = target.inject_error(
+        401,
+        Some(json!({
+            "errorCode": "hawkbit.server.error.unauthorized",
+            "message": "Bad token",
+        })),
+    );
+    match client.poll().await {
+        Err(Error::Unauthorized(e)) => {
+            assert_eq!(e.status(), reqwest::StatusCode::UNAUTHORIZED);
+            assert_eq!(e.error_code(), Some("hawkbit.server.error.unauthorized"));
+            assert_eq!(e.message(), Some("Bad token"));
+        }
+        r => panic!("unexpected poll result: {:?}", r),
+    }
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+
+    let mut mock = target.inject_error(410, None);
+    assert_matches!(client.poll().await, Err(Error::ActionClosed(_)));
+    mock.delete();
+
+    let mut mock = target.inject_error(429, None);
+    assert_matches!(client.poll().await, Err(Error::TooManyRequests(_)));
+    mock.delete();
+
+    let mut mock = target.inject_error(503, None);
+    assert_matches!(client.poll().await, Err(Error::ServerFailure(_)));
+    mock.delete();
+
+    let mut mock = target.inject_error(400, None);
+    let err = client.poll().await.unwrap_err();
+    assert_matches!(err, Error::HttpStatus(_));
+    assert_eq!(err.status(), Some(reqwest::StatusCode::BAD_REQUEST));
+    mock.delete();
+
+    // Back to normal
+    client.poll().await.expect("poll failed");
+}
+
+fn add_target_with_retry(server: &Server, name: &str, policy: RetryPolicy) -> (Client, Target) {
+    let target = server.add_target(name);
+
+    let client = ClientBuilder::new(
+        &server.base_url(),
+        &server.tenant,
+        &target.name,
+        &target.key,
+    )
+    .retry_policy(policy)
+    .build()
+    .expect("DDI creation failed");
+
+    (client, target)
+}
+
+#[tokio::test]
+async fn retry() {
+    use assert_matches::assert_matches;
+
+    init();
+
+    let server = ServerBuilder::default().build();
+    let policy = RetryPolicy::default()
+        .max_attempts(10)
+        .initial_backoff(Duration::from_millis(100))
+        .jitter(false);
+    let (client, target) = add_target_with_retry(&server, "Target1", policy);
+
+    // Server fails until the error is removed
+    let mut mock = target.inject_error(503, None);
+    let poll = tokio::spawn(async move { client.poll().await });
+    while mock.hits() < 2 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    mock.delete();
+
+    poll.await.unwrap().expect("poll failed");
+    assert_eq!(target.poll_hits(), 1);
+
+    // Give up after max attempts
+    let policy = RetryPolicy::default()
+        .max_attempts(3)
+        .initial_backoff(Duration::from_millis(10));
+    let (client, target) = add_target_with_retry(&server, "Target2", policy.clone());
+    let mut mock = target.inject_error(500, None);
+    assert_matches!(client.poll().await, Err(Error::ServerFailure(_)));
+    assert_eq!(mock.hits(), 3);
+    mock.delete();
+
+    // Permanent errors are not retried
+    let mut mock = target.inject_error(401, None);
+    assert_matches!(client.poll().await, Err(Error::Unauthorized(_)));
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+
+    // Custom predicate
+    let (client, target) = add_target_with_retry(
+        &server,
+        "Target3",
+        policy.retry_on(|e| e.status().is_none()),
+    );
+    let mut mock = target.inject_error(503, None);
+    assert_matches!(client.poll().await, Err(Error::ServerFailure(_)));
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+}
+
+#[tokio::test]
+async fn retry_after() {
+    use assert_matches::assert_matches;
+
+    init();
+
+    let server = ServerBuilder::default().build();
+    let policy = RetryPolicy::default()
+        .max_attempts(2)
+        .initial_backoff(Duration::from_millis(10));
+    let (client, target) = add_target_with_retry(&server, "Target1", policy);
+
+    let mut mock = target.inject_retry_after(429, 1);
+    let start = std::time::Instant::now();
+    match client.poll().await {
+        Err(Error::TooManyRequests(e)) => assert_eq!(e.retry_after(), Some(Duration::from_secs(1))),
+        r => panic!("unexpected poll result: {:?}", r),
+    }
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    assert_eq!(mock.hits(), 2);
+    mock.delete();
+
+    // Retried feedback
+    target.push_deployment(get_deployment(true));
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+
+    let mut error = target.inject_retry_after(503, 0);
+    let mut feedback = target.expect_deployment_feedback(
+        "10",
+        Execution::Proceeding,
+        Finished::None,
+        None,
+        vec!["Downloading"],
+    );
+    let send = update.send_feedback(Execution::Proceeding, Finished::None, vec!["Downloading"]);
+    assert_matches!(send.await, Err(Error::ServerFailure(_)));
+    assert_eq!(error.hits(), 2);
+    assert_eq!(feedback.hits(), 0);
+    error.delete();
+    feedback.delete();
+}
+
 #[tokio::test]
 async fn upload_config() {
     init();
@@ -112,18 +492,22 @@ fn artifact_path() -> PathBuf {
     test_artifact
 }
 
-fn get_deployment(valid_checksums: bool) -> Deployment {
-    let test_artifact = artifact_path();
+/// The artifact at `path`, having the same content as the one at [`artifact_path`],
+/// with its checksums.
+fn test_artifact(path: PathBuf) -> (PathBuf, &'static str, &'static str, &'static str) {
+    (
+        path,
+        "5eb63bbbe01eeed093cb22bb8f5acdc3",
+        "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed",
+        "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+    )
+}
 
+fn get_deployment(valid_checksums: bool) -> Deployment {
     let artifacts = if valid_checksums {
-        vec![(
-            test_artifact,
-            "5eb63bbbe01eeed093cb22bb8f5acdc3",
-            "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed",
-            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
-        )]
+        vec![test_artifact(artifact_path())]
     } else {
-        vec![(test_artifact, "badger", "badger", "badger")]
+        vec![(artifact_path(), "badger", "badger", "badger")]
     };
 
     DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
@@ -196,6 +580,10 @@ async fn deployment() {
         let art = chunk.artifacts().next().unwrap();
         assert_eq!(art.filename(), "test.txt");
         assert_eq!(art.size(), 11);
+        let url = format!("{}/download/test.txt", server.base_url());
+        let expected = |enabled: bool| if enabled { Some(url.as_str()) } else { None };
+        assert_eq!(art.https_url(), expected(p.https()));
+        assert_eq!(art.http_url(), expected(p.http()));
 
         let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
         let artifacts = chunk
@@ -218,6 +606,44 @@ async fn deployment() {
     }
 }
 
+#[tokio::test]
+async fn action_history() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let (client, target) = add_target(&server, "Target1");
+    let deploy = DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
+        .chunk(
+            ChunkProtocol::BOTH,
+            "app",
+            "1.0",
+            "some-chunk",
+            vec![test_artifact(artifact_path())],
+        )
+        .action_history("RUNNING", vec!["Downloaded", "Rebooting"])
+        .build();
+    target.push_deployment(deploy);
+
+    let reply = client.poll().await.expect("poll failed");
+
+    // the history is only provided if requested
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+    assert!(update.action_history().is_none());
+
+    let update = reply.update().expect("missing update");
+    let update = update
+        .fetch_with_history(10)
+        .await
+        .expect("failed to fetch update info");
+    let history = update.action_history().expect("missing action history");
+    assert_eq!(history.status(), "RUNNING");
+    assert_eq!(history.messages(), &["Downloaded", "Rebooting"]);
+    assert_eq!(update.chunks().count(), 1);
+
+    assert_eq!(target.deployment_hits(), 2);
+}
+
 #[tokio::test]
 async fn send_deployment_feedback() {
     init();
@@ -276,6 +702,27 @@ async fn send_deployment_feedback() {
         .expect("Failed to send feedback");
     assert_eq!(mock.hits(), 1);
     mock.delete();
+
+    // Send feedback with typed progress, code and timestamp
+    let feedback = DeploymentFeedback::new(Execution::Proceeding, Finished::None)
+        .progress(json!({"cnt": 2, "of": 5}))
+        .code(12)
+        .timestamp(1_600_000_000_000)
+        .details(vec!["Installing"]);
+    let mut mock = target.expect_deployment_feedback_with(&deploy_id, feedback);
+    assert_eq!(mock.hits(), 0);
+
+    update
+        .feedback(Execution::Proceeding, Finished::None)
+        .progress(hawkbit::ddi::Progress::new(2, 5))
+        .code(12)
+        .timestamp(UNIX_EPOCH + Duration::from_millis(1_600_000_000_000))
+        .details(vec!["Installing"])
+        .send()
+        .await
+        .expect("Failed to send feedback");
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
 }
 
 #[tokio::test]
@@ -339,7 +786,7 @@ async fn download_stream() {
         }
 
         // Compare downloaded content with the actual file
-        let mut art_file = File::open(&artifact_path()).expect("failed to open artifact");
+        let mut art_file = File::open(artifact_path()).expect("failed to open artifact");
         let mut expected = Vec::new();
         art_file
             .read_to_end(&mut expected)
@@ -386,6 +833,138 @@ async fn download_stream() {
     }
 }
 
+#[tokio::test]
+async fn md5sum() {
+    init();
+
+    let server = ServerBuilder::default().build();
+
+    for (i, valid) in [true, false].iter().enumerate() {
+        let (client, target) = add_target(&server, &format!("Target{}", i));
+        target.push_deployment(get_deployment(*valid));
+
+        let reply = client.poll().await.expect("poll failed");
+        let update = reply.update().expect("missing update");
+        let update = update.fetch().await.expect("failed to fetch update info");
+
+        // md5sum files are provided for each chunk protocol
+        for chunk in update.chunks() {
+            let art = chunk.artifacts().next().unwrap();
+            let md5sum = art.fetch_md5sum().await;
+
+            if *valid {
+                assert_eq!(
+                    md5sum.expect("failed to fetch md5sum").as_deref(),
+                    Some("5eb63bbbe01eeed093cb22bb8f5acdc3")
+                );
+            } else {
+                assert!(matches!(md5sum, Err(Error::InvalidMd5sum(_))));
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn link_policy() {
+    use hawkbit::ddi::LinkPolicy;
+
+    init();
+
+    let server = ServerBuilder::default().build();
+
+    async fn download(
+        server: &Server,
+        name: &str,
+        policy: LinkPolicy,
+        deploy: DeploymentBuilder,
+    ) -> Result<DownloadedArtifact, Error> {
+        let target = server.add_target(name);
+        let client = ClientBuilder::new(
+            &server.base_url(),
+            &server.tenant,
+            &target.name,
+            &target.key,
+        )
+        .link_policy(policy)
+        .build()
+        .expect("DDI creation failed");
+        target.push_deployment(deploy.build());
+
+        let reply = client.poll().await.expect("poll failed");
+        let update = reply.update().expect("missing update");
+        let update = update.fetch().await.expect("failed to fetch update info");
+        let chunk = update.chunks().next().unwrap();
+        let art = chunk.artifacts().next().unwrap();
+
+        let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+        art.download(out_dir.path()).await
+    }
+
+    let deploy = |protocol| {
+        DeploymentBuilder::new("10", Type::Forced, Type::Attempt).chunk(
+            protocol,
+            "app",
+            "1.0",
+            "some-chunk",
+            vec![test_artifact(artifact_path())],
+        )
+    };
+    // nothing is listening on this port
+    let unreachable = |protocol| deploy(protocol).https_base_url("http://127.0.0.1:1/download");
+
+    // links not allowed by the policy are never used
+    let res = download(
+        &server,
+        "Target1",
+        LinkPolicy::HttpsOnly,
+        deploy(ChunkProtocol::HTTP),
+    )
+    .await;
+    assert!(matches!(res, Err(Error::MissingLink(name)) if name == "test.txt"));
+    let res = download(
+        &server,
+        "Target2",
+        LinkPolicy::HttpOnly,
+        deploy(ChunkProtocol::HTTPS),
+    )
+    .await;
+    assert!(matches!(res, Err(Error::MissingLink(_))));
+    let res = download(
+        &server,
+        "Target3",
+        LinkPolicy::HttpsOnly,
+        unreachable(ChunkProtocol::BOTH),
+    )
+    .await;
+    assert!(matches!(res, Err(Error::ReqwestError(e)) if e.is_connect()));
+
+    // the http link is used if https is not provided or cannot be reached
+    let res = download(
+        &server,
+        "Target4",
+        LinkPolicy::PreferHttps,
+        deploy(ChunkProtocol::HTTP),
+    )
+    .await;
+    assert!(res.is_ok());
+    let res = download(
+        &server,
+        "Target5",
+        LinkPolicy::PreferHttps,
+        unreachable(ChunkProtocol::BOTH),
+    )
+    .await;
+    assert!(res.is_ok());
+    let res = download(
+        &server,
+        "Target6",
+        LinkPolicy::HttpOnly,
+        unreachable(ChunkProtocol::BOTH),
+    )
+    .await;
+    assert!(res.is_ok());
+}
+
 #[cfg(feature = "hash-digest")]
 #[tokio::test]
 async fn wrong_checksums() {
@@ -461,21 +1040,312 @@ async fn wrong_checksums() {
     }
 }
 
+#[cfg(feature = "hash-sha256")]
 #[tokio::test]
-async fn cancel_action() {
+async fn verification_policy() {
+    use hawkbit::ddi::{ChecksumType, VerificationPolicy};
+
     init();
 
     let server = ServerBuilder::default().build();
     let (client, target) = add_target(&server, "Target1");
-    target.cancel_action("10");
 
-    let reply = client.poll().await.expect("poll failed");
-    assert!(reply.config_data_request().is_none());
-    assert!(reply.update().is_none());
-    let cancel_action = reply.cancel_action().expect("missing cancel action");
+    // only the sha256 checksum is valid
+    let deployment = DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
+        .chunk(
+            ChunkProtocol::HTTP,
+            "app",
+            "1.0",
+            "some-chunk",
+            vec![(
+                artifact_path(),
+                "badger",
+                "badger",
+                "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+            )],
+        )
+        .build();
+    target.push_deployment(deployment);
 
-    let id = cancel_action
-        .id()
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+    let chunk = update.chunks().next().unwrap();
+    let art = chunk.artifacts().next().unwrap();
+
+    let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let options =
+        DownloadOptions::default().verification(VerificationPolicy::Require(ChecksumType::Sha256));
+    let downloaded = art
+        .download_with_options(out_dir.path(), &options)
+        .await
+        .expect("failed to download artifact");
+    assert_eq!(*downloaded.file(), out_dir.path().join("test.txt"));
+
+    cfg_if::cfg_if! {
+        if #[cfg(any(feature = "hash-md5", feature = "hash-sha1"))] {
+            use assert_matches::assert_matches;
+
+            // corrupted artifacts are not kept
+            let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+            let options = DownloadOptions::default().verification(VerificationPolicy::AllAvailable);
+            let res = art.download_with_options(out_dir.path(), &options).await;
+            assert_matches!(res, Err(Error::ChecksumError(_)));
+            assert!(!out_dir.path().join("test.txt").exists());
+            assert!(!out_dir.path().join("test.txt.part").exists());
+        }
+    }
+}
+
+#[tokio::test]
+async fn confirmation() {
+    use hawkbit::ddi::ConfirmationResponse;
+
+    init();
+
+    let server = ServerBuilder::default().build();
+    let (client, target) = add_target(&server, "Target1");
+    let deploy = DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
+        .maintenance_window(MaintenanceWindow::Available)
+        .chunk(
+            ChunkProtocol::BOTH,
+            "app",
+            "1.0",
+            "some-chunk",
+            vec![test_artifact(artifact_path())],
+        )
+        .action_history("WAIT_FOR_CONFIRMATION", vec!["Waiting"])
+        .build();
+    target.request_confirmation(deploy);
+
+    let reply = client.poll().await.expect("poll failed");
+    assert!(reply.update().is_none());
+    let request = reply
+        .confirmation_request()
+        .expect("missing confirmation request");
+    assert_eq!(target.confirmation_hits(), 0);
+
+    let confirmation = request.fetch().await.expect("failed to fetch confirmation");
+    assert_eq!(target.confirmation_hits(), 1);
+    assert_eq!(confirmation.id(), "10");
+    assert_eq!(confirmation.download_type(), Type::Forced);
+    assert_eq!(confirmation.update_type(), Type::Attempt);
+    assert_eq!(
+        confirmation.maintenance_window(),
+        Some(MaintenanceWindow::Available)
+    );
+    let history = confirmation.action_history().expect("missing history");
+    assert_eq!(history.status(), "WAIT_FOR_CONFIRMATION");
+    let chunk = confirmation.chunks().next().expect("missing chunk");
+    let art = chunk.artifacts().next().expect("missing artifact");
+    assert_eq!(art.filename(), "test.txt");
+    assert_eq!(art.size(), 11);
+
+    // deny, then confirm
+    let mut mock = target.expect_confirmation_feedback(
+        "10",
+        ConfirmationResponse::Denied,
+        None,
+        vec!["Postponed by the user"],
+    );
+    request
+        .send_feedback(
+            ConfirmationResponse::Denied,
+            None,
+            vec!["Postponed by the user"],
+        )
+        .await
+        .expect("failed to send feedback");
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+
+    let mut mock = target.expect_confirmation_feedback(
+        "10",
+        ConfirmationResponse::Confirmed,
+        Some(12),
+        vec!["Accepted by the user"],
+    );
+    request
+        .feedback(ConfirmationResponse::Confirmed)
+        .code(12)
+        .details(vec!["Accepted by the user"])
+        .send()
+        .await
+        .expect("failed to send feedback");
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+
+    // auto-confirmation
+    let mut mock = target.expect_auto_confirm_activation(Some("user"), None);
+    request
+        .activate_auto_confirm(Some("user"), None)
+        .await
+        .expect("failed to activate auto-confirm");
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+
+    let mut mock = target.expect_auto_confirm_deactivation();
+    request
+        .deactivate_auto_confirm()
+        .await
+        .expect("failed to deactivate auto-confirm");
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+}
+
+#[tokio::test]
+async fn auto_confirm() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let (client, target) = add_target(&server, "Target1");
+
+    // no action is waiting for confirmation
+    let mut mock = target.expect_auto_confirm_state(false, None, None);
+    let state = client.auto_confirm().await.expect("failed to fetch state");
+    assert!(!state.active());
+    assert_eq!(state.initiator(), None);
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+
+    let mut mock = target.expect_auto_confirm_activation(Some("user"), Some("trusted"));
+    client
+        .activate_auto_confirm(Some("user"), Some("trusted"))
+        .await
+        .expect("failed to activate auto-confirm");
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+
+    let mut mock = target.expect_auto_confirm_state(true, Some("user"), Some("trusted"));
+    let state = client.auto_confirm().await.expect("failed to fetch state");
+    assert!(state.active());
+    assert_eq!(state.initiator(), Some("user"));
+    assert_eq!(state.remark(), Some("trusted"));
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+
+    let mut mock = target.expect_auto_confirm_deactivation();
+    client
+        .deactivate_auto_confirm()
+        .await
+        .expect("failed to deactivate auto-confirm");
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+}
+
+#[tokio::test]
+async fn installed() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let (client, target) = add_target(&server, "Target1");
+
+    let reply = client.poll().await.expect("poll failed");
+    assert!(reply.installed().is_none());
+
+    let deploy = DeploymentBuilder::new("5", Type::Forced, Type::Forced)
+        .chunk(
+            ChunkProtocol::BOTH,
+            "os",
+            "2.1",
+            "rootfs",
+            vec![test_artifact(artifact_path())],
+        )
+        .build();
+    target.set_installed(deploy);
+
+    let reply = client.poll().await.expect("poll failed");
+    assert!(reply.update().is_none());
+    let installed = reply.installed().expect("missing installed action");
+    let installed = installed
+        .fetch()
+        .await
+        .expect("failed to fetch installed action");
+    assert_eq!(target.installed_hits(), 1);
+    assert_eq!(installed.id(), "5");
+    assert_eq!(installed.download_type(), Type::Forced);
+    assert_eq!(installed.update_type(), Type::Forced);
+
+    let chunk = installed.chunks().next().expect("missing chunk");
+    assert_eq!(chunk.part(), "os");
+    assert_eq!(chunk.version(), "2.1");
+    assert_eq!(chunk.name(), "rootfs");
+
+    // the installed artifacts can be downloaded again
+    let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let downloaded = installed
+        .download(out_dir.path())
+        .await
+        .expect("failed to download artifacts");
+    assert_eq!(downloaded.len(), 1);
+    assert_eq!(std::fs::read(downloaded[0].file()).unwrap(), b"hello world");
+}
+
+#[tokio::test]
+async fn software_module() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let (client, target) = add_target(&server, "Target1");
+
+    let deploy = || {
+        DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
+            .chunk(
+                ChunkProtocol::BOTH,
+                "app",
+                "1.0",
+                "some-chunk",
+                vec![test_artifact(artifact_path())],
+            )
+            .chunk_id("some-chunk", 42)
+            .build()
+    };
+    target.push_deployment(deploy());
+    target.add_software_modules(deploy());
+
+    // the module id is provided by the deployment
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+    let chunk = update.chunks().next().unwrap();
+    assert_eq!(chunk.id(), Some(42));
+
+    let module = client
+        .software_module_artifacts(42)
+        .await
+        .expect("failed to fetch software module");
+    assert_eq!(module.id(), 42);
+    assert_eq!(module.artifacts().count(), 1);
+    let art = module.artifacts().next().unwrap();
+    assert_eq!(art.filename(), "test.txt");
+    assert_eq!(art.size(), 11);
+
+    let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let downloaded = art
+        .download(out_dir.path())
+        .await
+        .expect("failed to download artifact");
+    assert_eq!(std::fs::read(downloaded.file()).unwrap(), b"hello world");
+
+    let res = client.software_module_artifacts(43).await;
+    assert!(matches!(res, Err(Error::NotFound(_))));
+}
+
+#[tokio::test]
+async fn cancel_action() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let (client, target) = add_target(&server, "Target1");
+    target.cancel_action("10");
+
+    let reply = client.poll().await.expect("poll failed");
+    assert!(reply.config_data_request().is_none());
+    assert!(reply.update().is_none());
+    let cancel_action = reply.cancel_action().expect("missing cancel action");
+
+    let id = cancel_action
+        .id()
         .await
         .expect("failed to fetch cancel action id");
     assert_eq!(id, "10");
@@ -497,4 +1367,1338 @@ async fn cancel_action() {
         .expect("Failed to send feedback");
     assert_eq!(mock.hits(), 1);
     mock.delete();
+
+    let mut mock =
+        target.expect_cancel_feedback(&id, Execution::Closed, Finished::Success, vec!["Cancelled"]);
+    cancel_action
+        .feedback(Execution::Closed, Finished::Success)
+        .details(vec!["Cancelled"])
+        .send()
+        .await
+        .expect("Failed to send feedback");
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+}
+
+struct TestHandler {
+    token: CancellationToken,
+    updates: std::sync::Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl UpdateHandler for TestHandler {
+    type Config = serde_json::Value;
+
+    async fn on_config_request(&self) -> Self::Config {
+        json!({"awesome": true})
+    }
+
+    async fn on_update(&self, update: &Update, artifacts: Vec<DownloadedArtifact>) -> Outcome {
+        assert_eq!(update.chunks().count(), 3);
+        for artifact in artifacts {
+            let name = artifact.file().file_name().unwrap().to_str().unwrap();
+            self.updates.lock().unwrap().push(name.to_string());
+        }
+        Outcome::Success(vec!["Installed".to_string()])
+    }
+
+    async fn on_cancel(&self, action_id: &str) -> Outcome {
+        assert_eq!(action_id, "10");
+        // cancel is the last request processed, stop the agent
+        self.token.cancel();
+        Outcome::Failure(vec!["Already installed".to_string()])
+    }
+
+    async fn on_resume(&self, state: &ActionState) -> Outcome {
+        assert_eq!(state.phase(), Phase::Installing);
+        self.token.cancel();
+        Outcome::Success(vec!["Rebooted".to_string()])
+    }
+}
+
+#[tokio::test]
+async fn agent() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let (client, target) = add_target(&server, "Target1");
+
+    target.request_config(json!({
+        "mode" : null,
+        "data" : {
+            "awesome" : true,
+        },
+        "status" : {
+            "result" : {
+            "finished" : "success"
+            },
+            "execution" : "closed",
+            "details" : []
+        }
+    }));
+    let deploy = get_deployment(true);
+    let deploy_id = deploy.id.clone();
+    target.push_deployment(deploy);
+    target.cancel_action("10");
+
+    let downloading = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Proceeding,
+        Finished::None,
+        None,
+        vec!["Downloading"],
+    );
+    let installed = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Closed,
+        Finished::Success,
+        None,
+        vec!["Installed"],
+    );
+    let rejected = target.expect_cancel_feedback(
+        "10",
+        Execution::Rejected,
+        Finished::Failure,
+        vec!["Already installed"],
+    );
+
+    let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let token = CancellationToken::new();
+    let handler = TestHandler {
+        token: token.clone(),
+        updates: std::sync::Mutex::new(vec![]),
+    };
+    let agent = Agent::new(client, handler, out_dir.path());
+
+    // the agent stops by itself once the cancel action has been handled
+    tokio::time::timeout(Duration::from_secs(10), agent.run(token))
+        .await
+        .expect("agent did not stop");
+
+    assert_eq!(target.poll_hits(), 1);
+    assert_eq!(target.config_data_hits(), 1);
+    assert_eq!(target.deployment_hits(), 1);
+    assert_eq!(target.cancel_action_hits(), 1);
+    assert_eq!(downloading.hits(), 1);
+    assert_eq!(installed.hits(), 1);
+    assert_eq!(rejected.hits(), 1);
+    assert_eq!(agent.handler().updates.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn resume_action() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let deploy = get_deployment(true);
+    let deploy_id = deploy.id.clone();
+    let (client, target) = add_target(&server, "Target1");
+    target.push_deployment(deploy);
+
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+
+    let state_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let path = state_dir.path().join("action.json");
+    assert_eq!(ActionState::load(&path).await.expect("load failed"), None);
+
+    let state = update
+        .action_state(Phase::Installing)
+        .expect("failed to get state");
+    assert_eq!(state.id(), deploy_id);
+    assert_eq!(state.phase(), Phase::Installing);
+    state.save(&path).await.expect("save failed");
+    assert!(!state_dir.path().join("action.json.tmp").exists());
+    drop(update);
+
+    // device reboots and creates a new client
+    let client = Client::new(
+        &server.base_url(),
+        &server.tenant,
+        &target.name,
+        &target.key,
+    )
+    .expect("DDI creation failed");
+    let state = ActionState::load(&path)
+        .await
+        .expect("load failed")
+        .expect("missing state");
+    assert_eq!(state.id(), deploy_id);
+    assert_eq!(state.phase(), Phase::Installing);
+    let resumed = client.resume(state.clone());
+
+    let mut mock = target.expect_deployment_feedback_with(
+        &deploy_id,
+        DeploymentFeedback::new(Execution::Proceeding, Finished::None)
+            .progress(json!({"cnt": 3, "of": 4}))
+            .code(42)
+            .details(vec!["Rebooting"]),
+    );
+    resumed
+        .feedback(Execution::Proceeding, Finished::None)
+        .progress(hawkbit::ddi::Progress::new(3, 4))
+        .code(42)
+        .details(vec!["Rebooting"])
+        .send()
+        .await
+        .expect("Failed to send feedback");
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+
+    let mut mock = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Closed,
+        Finished::Success,
+        None,
+        vec!["Rebooted"],
+    );
+    client
+        .resume(state)
+        .send_feedback(Execution::Closed, Finished::Success, vec!["Rebooted"])
+        .await
+        .expect("Failed to send feedback");
+    assert_eq!(mock.hits(), 1);
+    mock.delete();
+
+    ActionState::remove(&path).await.expect("remove failed");
+    assert_eq!(ActionState::load(&path).await.expect("load failed"), None);
+}
+
+#[tokio::test]
+async fn agent_resume() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let deploy = get_deployment(true);
+    let deploy_id = deploy.id.clone();
+    let (client, target) = add_target(&server, "Target1");
+    target.push_deployment(deploy);
+
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+
+    let state_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let path = state_dir.path().join("action.json");
+    update
+        .action_state(Phase::Installing)
+        .expect("failed to get state")
+        .save(&path)
+        .await
+        .expect("save failed");
+
+    let mock = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Closed,
+        Finished::Success,
+        None,
+        vec!["Rebooted"],
+    );
+
+    let token = CancellationToken::new();
+    let handler = TestHandler {
+        token: token.clone(),
+        updates: std::sync::Mutex::new(vec![]),
+    };
+    let agent = Agent::new(client, handler, state_dir.path()).state_file(&path);
+
+    // the handler stops the agent once the update has been resumed
+    tokio::time::timeout(Duration::from_secs(10), agent.run(token))
+        .await
+        .expect("agent did not stop");
+
+    assert_eq!(mock.hits(), 1);
+    assert!(!path.exists());
+    // the agent did not poll as it has been stopped while resuming
+    assert_eq!(target.poll_hits(), 1);
+}
+
+// Handler failing if an update is installed
+struct NoInstallHandler {
+    token: std::sync::Mutex<CancellationToken>,
+}
+
+impl NoInstallHandler {
+    fn new(token: &CancellationToken) -> Self {
+        Self {
+            token: std::sync::Mutex::new(token.clone()),
+        }
+    }
+
+    /// Return a new token to run the agent again.
+    fn restart(&self) -> CancellationToken {
+        let token = CancellationToken::new();
+        *self.token.lock().unwrap() = token.clone();
+        token
+    }
+}
+
+#[async_trait]
+impl UpdateHandler for NoInstallHandler {
+    type Config = serde_json::Value;
+
+    async fn on_config_request(&self) -> Self::Config {
+        json!({})
+    }
+
+    async fn on_update(&self, _update: &Update, _artifacts: Vec<DownloadedArtifact>) -> Outcome {
+        panic!("update installed");
+    }
+
+    async fn on_cancel(&self, _action_id: &str) -> Outcome {
+        // cancel is the last request processed, stop the agent
+        self.token.lock().unwrap().cancel();
+        Outcome::Success(vec![])
+    }
+
+    async fn on_resume(&self, _state: &ActionState) -> Outcome {
+        Outcome::Success(vec!["Rebooted".to_string()])
+    }
+}
+
+#[tokio::test]
+async fn agent_resume_retry() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let deploy = get_deployment(true);
+    let deploy_id = deploy.id.clone();
+    let (client, target) = add_target(&server, "Target1");
+    target.push_deployment(deploy);
+    target.cancel_action("10");
+
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+
+    let state_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let path = state_dir.path().join("action.json");
+    update
+        .action_state(Phase::Installing)
+        .expect("failed to get state")
+        .save(&path)
+        .await
+        .expect("save failed");
+    let downloading = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Proceeding,
+        Finished::None,
+        None,
+        vec!["Downloading"],
+    );
+
+    // the server does not accept the feedback yet, so the update is not processed
+    let token = CancellationToken::new();
+    let handler = NoInstallHandler::new(&token);
+    let agent = Agent::new(client, handler, state_dir.path()).state_file(&path);
+    tokio::time::timeout(Duration::from_secs(10), agent.run(token))
+        .await
+        .expect("agent did not stop");
+
+    assert!(path.exists());
+    assert_eq!(target.poll_hits(), 2);
+    assert_eq!(target.deployment_hits(), 1);
+
+    // the feedback is accepted, the update pending on the server is skipped
+    let rebooted = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Closed,
+        Finished::Success,
+        None,
+        vec!["Rebooted"],
+    );
+    let client = Client::new(
+        &server.base_url(),
+        &server.tenant,
+        &target.name,
+        &target.key,
+    )
+    .expect("DDI creation failed");
+    let token = CancellationToken::new();
+    let handler = NoInstallHandler::new(&token);
+    let agent = Agent::new(client, handler, state_dir.path()).state_file(&path);
+    tokio::time::timeout(Duration::from_secs(10), agent.run(token))
+        .await
+        .expect("agent did not stop");
+
+    assert_eq!(rebooted.hits(), 1);
+    assert!(!path.exists());
+    assert_eq!(target.poll_hits(), 3);
+    assert_eq!(target.deployment_hits(), 2);
+    assert_eq!(downloading.hits(), 0);
+}
+
+#[tokio::test]
+async fn agent_deferred_update() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let artifacts = vec![test_artifact(artifact_path())];
+    let outside_window = DeploymentBuilder::new("10", Type::Forced, Type::Forced)
+        .maintenance_window(MaintenanceWindow::Unavailable)
+        .chunk(
+            ChunkProtocol::HTTP,
+            "app",
+            "1.0",
+            "some-chunk",
+            artifacts.clone(),
+        )
+        .build();
+    let skipped = DeploymentBuilder::new("10", Type::Forced, Type::Skip)
+        .chunk(ChunkProtocol::HTTP, "app", "1.0", "some-chunk", artifacts)
+        .build();
+
+    for (name, deploy) in [("Target1", outside_window), ("Target2", skipped)] {
+        let (client, target) = add_target(&server, name);
+        target.push_deployment(deploy);
+        target.cancel_action("10");
+
+        let downloading = target.expect_deployment_feedback(
+            "10",
+            Execution::Proceeding,
+            Finished::None,
+            None,
+            vec!["Downloading"],
+        );
+        let waiting = target.expect_deployment_feedback(
+            "10",
+            Execution::Proceeding,
+            Finished::None,
+            None,
+            vec!["Waiting for installation"],
+        );
+
+        let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+        let token = CancellationToken::new();
+        let handler = NoInstallHandler::new(&token);
+        let agent = Agent::new(client, handler, out_dir.path());
+        tokio::time::timeout(Duration::from_secs(10), agent.run(token))
+            .await
+            .expect("agent did not stop");
+
+        // the artifacts are downloaded but not installed
+        assert_eq!(downloading.hits(), 1);
+        assert_eq!(waiting.hits(), 1);
+        assert!(out_dir.path().join("some-chunk").join("test.txt").exists());
+
+        // the installation is still not allowed at the next polling
+        let token = agent.handler().restart();
+        tokio::time::timeout(Duration::from_secs(10), agent.run(token))
+            .await
+            .expect("agent did not stop");
+
+        assert_eq!(target.poll_hits(), 2);
+        assert_eq!(target.download_hits("test.txt"), 1);
+        assert_eq!(downloading.hits(), 1);
+        assert_eq!(waiting.hits(), 1);
+    }
+}
+
+#[tokio::test]
+async fn feedback_journal() {
+    use assert_matches::assert_matches;
+
+    init();
+
+    let server = ServerBuilder::default().build();
+    let deploy = get_deployment(true);
+    let deploy_id = deploy.id.clone();
+    let target = server.add_target("Target1");
+    target.push_deployment(deploy);
+
+    let journal_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let journal = journal_dir.path().join("journal.json");
+    let client = ClientBuilder::new(
+        &server.base_url(),
+        &server.tenant,
+        &target.name,
+        &target.key,
+    )
+    .feedback_journal(&journal)
+    .build()
+    .expect("DDI creation failed");
+
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+
+    // server goes down, feedback are queued
+    let mut error = target.inject_error(503, None);
+    for _ in 0..2 {
+        update
+            .send_feedback(Execution::Proceeding, Finished::None, vec!["Downloading"])
+            .await
+            .expect("Failed to queue feedback");
+    }
+    assert_eq!(client.pending_feedback().await, 1);
+    assert!(journal.exists());
+    assert_matches!(client.poll().await, Err(Error::ServerFailure(_)));
+    assert_eq!(client.pending_feedback().await, 1);
+
+    // server is back, feedback are sent when polling
+    error.delete();
+    let mut downloading = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Proceeding,
+        Finished::None,
+        None,
+        vec!["Downloading"],
+    );
+    client.poll().await.expect("poll failed");
+    assert_eq!(client.pending_feedback().await, 0);
+    assert_eq!(downloading.hits(), 1);
+    downloading.delete();
+
+    // feedback rejected as unauthorized are reported and kept queued
+    let mut error = target.inject_error(503, None);
+    update
+        .send_feedback(Execution::Proceeding, Finished::None, vec!["Downloading"])
+        .await
+        .expect("Failed to queue feedback");
+    error.delete();
+    let mut error = target.inject_error(401, None);
+    let res = update
+        .send_feedback(Execution::Proceeding, Finished::None, vec!["Verifying"])
+        .await;
+    assert_matches!(res, Err(Error::Unauthorized(_)));
+    assert_eq!(client.pending_feedback().await, 2);
+    assert_matches!(client.replay_feedback().await, Err(Error::Unauthorized(_)));
+    assert_eq!(client.pending_feedback().await, 2);
+
+    error.delete();
+    let mut downloading = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Proceeding,
+        Finished::None,
+        None,
+        vec!["Downloading"],
+    );
+    let mut verifying = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Proceeding,
+        Finished::None,
+        None,
+        vec!["Verifying"],
+    );
+    client.replay_feedback().await.expect("replay failed");
+    assert_eq!(client.pending_feedback().await, 0);
+    assert_eq!(downloading.hits(), 1);
+    assert_eq!(verifying.hits(), 1);
+    downloading.delete();
+    verifying.delete();
+
+    // feedback rejected by the server are dropped, the sender gets the error
+    let mut error = target.inject_error(503, None);
+    update
+        .send_feedback(Execution::Proceeding, Finished::None, vec!["Downloading"])
+        .await
+        .expect("Failed to queue feedback");
+    error.delete();
+    let mut error = target.inject_error(410, None);
+    let res = update
+        .send_feedback(Execution::Proceeding, Finished::None, vec!["Verifying"])
+        .await;
+    assert_matches!(res, Err(Error::ActionClosed(_)));
+    assert_eq!(client.pending_feedback().await, 0);
+    error.delete();
+
+    // server goes down again, the closed feedback replaces the pending one
+    let mut error = target.inject_error(503, None);
+    update
+        .send_feedback(Execution::Proceeding, Finished::None, vec!["Installing"])
+        .await
+        .expect("Failed to queue feedback");
+    update
+        .send_feedback(Execution::Closed, Finished::Success, vec![])
+        .await
+        .expect("Failed to queue feedback");
+    update
+        .send_feedback(Execution::Proceeding, Finished::None, vec!["Installing"])
+        .await
+        .expect("Failed to queue feedback");
+    assert_eq!(client.pending_feedback().await, 1);
+    drop(client);
+
+    // the journal is preserved by a new client, which sends it once the server is back
+    error.delete();
+    let installing = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Proceeding,
+        Finished::None,
+        None,
+        vec!["Installing"],
+    );
+    let closed = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Closed,
+        Finished::Success,
+        None,
+        vec![],
+    );
+    let client = ClientBuilder::new(
+        &server.base_url(),
+        &server.tenant,
+        &target.name,
+        &target.key,
+    )
+    .feedback_journal(&journal)
+    .build()
+    .expect("DDI creation failed");
+    assert_eq!(client.pending_feedback().await, 1);
+    client.replay_feedback().await.expect("replay failed");
+    assert_eq!(client.pending_feedback().await, 0);
+    assert_eq!(installing.hits(), 0);
+    assert_eq!(closed.hits(), 1);
+}
+
+#[tokio::test]
+async fn resume_download() {
+    #[cfg(feature = "hash-digest")]
+    use assert_matches::assert_matches;
+    use hawkbit::ddi::VerificationPolicy;
+
+    init();
+
+    let server = ServerBuilder::default().build();
+
+    for range_requests in &[true, false] {
+        let (client, target) = add_target(&server, &format!("Target-{}", range_requests));
+        let deploy = DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
+            .range_requests(if *range_requests { &[5] } else { &[] })
+            .chunk(
+                ChunkProtocol::BOTH,
+                "app",
+                "1.0",
+                "some-chunk",
+                vec![test_artifact(artifact_path())],
+            )
+            .build();
+        target.push_deployment(deploy);
+
+        let reply = client.poll().await.expect("poll failed");
+        let update = reply.update().expect("missing update");
+        let update = update.fetch().await.expect("failed to fetch update info");
+        let chunk = update.chunks().next().unwrap();
+        let art = chunk.artifacts().next().unwrap();
+
+        let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+        let path = out_dir.path().join("test.txt");
+        let part = out_dir.path().join("test.txt.part");
+        let resume = DownloadOptions::default()
+            .resume(true)
+            .verification(VerificationPolicy::AllAvailable);
+
+        // partial file left by a previous download, with different content so we can
+        // check which part has been downloaded again
+        std::fs::write(&part, "HELLO").unwrap();
+        let res = art.download_with_options(out_dir.path(), &resume).await;
+        if *range_requests {
+            cfg_if::cfg_if! {
+                if #[cfg(feature = "hash-digest")] {
+                    // the resumed file is corrupted so it is removed
+                    assert_matches!(res, Err(Error::ChecksumError(_)));
+                    assert!(!path.exists());
+                } else {
+                    let downloaded = res.expect("Failed to download artifact");
+                    assert_eq!(
+                        std::fs::read_to_string(downloaded.file()).unwrap(),
+                        "HELLO world"
+                    );
+                }
+            }
+        } else {
+            // server does not support range requests, the whole file has been downloaded
+            let downloaded = res.expect("Failed to download artifact");
+            assert_eq!(
+                std::fs::read_to_string(downloaded.file()).unwrap(),
+                "hello world"
+            );
+        }
+        assert!(!part.exists());
+
+        // proper partial file, the downloaded file is complete
+        std::fs::write(&part, "hello").unwrap();
+        let downloaded = art
+            .download_with_options(out_dir.path(), &resume)
+            .await
+            .expect("Failed to download artifact");
+        assert_eq!(
+            std::fs::read_to_string(downloaded.file()).unwrap(),
+            "hello world"
+        );
+        #[cfg(feature = "hash-sha256")]
+        downloaded.check_sha256().await.expect("invalid sha256");
+
+        assert_eq!(downloaded.file(), &path);
+        assert!(!part.exists());
+
+        // complete partial file, it is verified without downloading it again
+        let hits = target.download_hits("test.txt");
+        std::fs::write(&part, "hello world").unwrap();
+        let downloaded = art
+            .download_with_options(out_dir.path(), &resume)
+            .await
+            .expect("Failed to download artifact");
+        assert_eq!(
+            std::fs::read_to_string(downloaded.file()).unwrap(),
+            "hello world"
+        );
+        assert_eq!(target.download_hits("test.txt"), hits);
+        assert!(!part.exists());
+
+        // resuming is disabled by default
+        std::fs::write(&part, "HELLO").unwrap();
+        let downloaded = art
+            .download(out_dir.path())
+            .await
+            .expect("Failed to download artifact");
+        assert_eq!(
+            std::fs::read_to_string(downloaded.file()).unwrap(),
+            "hello world"
+        );
+        assert!(!part.exists());
+    }
+}
+
+#[tokio::test]
+async fn download_progress() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let deploy = get_deployment(true);
+    let deploy_id = deploy.id.clone();
+    let (client, target) = add_target(&server, "Target1");
+    target.push_deployment(deploy);
+
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+
+    let started = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Proceeding,
+        Finished::None,
+        Some(json!({"cnt": 0, "of": 100})),
+        vec!["Downloading"],
+    );
+    let done = target.expect_deployment_feedback(
+        &deploy_id,
+        Execution::Proceeding,
+        Finished::None,
+        Some(json!({"cnt": 100, "of": 100})),
+        vec!["Downloading"],
+    );
+
+    let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let options = {
+        let reports = reports.clone();
+        DownloadOptions::default()
+            .progress(move |p| reports.lock().unwrap().push(p.clone()))
+            .progress_feedback(Duration::from_secs(3600))
+    };
+
+    let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let artifacts = update
+        .download_with_options(out_dir.path(), &options)
+        .await
+        .expect("Failed to download update");
+    assert_eq!(artifacts.len(), 3);
+
+    // feedback are rate limited, only the start and the completion are reported
+    assert_eq!(started.hits(), 1);
+    assert_eq!(done.hits(), 1);
+
+    let reports = reports.lock().unwrap();
+    let first = reports.first().unwrap();
+    assert_eq!(first.artifact(), "test.txt");
+    assert_eq!(first.downloaded(), 0);
+    assert_eq!(first.total(), 11);
+    assert_eq!(first.update_downloaded(), 0);
+    assert_eq!(first.update_total(), 33);
+    let last = reports.last().unwrap();
+    assert_eq!(last.downloaded(), 11);
+    assert_eq!(last.total(), 11);
+    assert_eq!(last.update_downloaded(), 33);
+    assert_eq!(last.update_total(), 33);
+    assert!(reports
+        .windows(2)
+        .all(|w| w[0].update_downloaded() <= w[1].update_downloaded()));
+}
+
+#[tokio::test]
+async fn download_progress_slow_server() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let deploy = get_deployment(true);
+    let deploy_id = deploy.id.clone();
+    let (client, target) = add_target(&server, "Target1");
+    target.push_deployment(deploy);
+
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+
+    // the download goes on while the first feedback is being sent
+    let feedback = |cnt| {
+        DeploymentFeedback::new(Execution::Proceeding, Finished::None)
+            .progress(json!({"cnt": cnt, "of": 100}))
+            .details(vec!["Downloading"])
+    };
+    let ongoing: Vec<_> = [0, 33, 66]
+        .iter()
+        .map(|cnt| {
+            let feedback = feedback(*cnt).reply_delay(Duration::from_secs(2));
+            target.expect_deployment_feedback_with(&deploy_id, feedback)
+        })
+        .collect();
+    let done = target.expect_deployment_feedback_with(&deploy_id, feedback(100));
+
+    let options = DownloadOptions::default().progress_feedback(Duration::ZERO);
+    let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    update
+        .download_with_options(out_dir.path(), &options)
+        .await
+        .expect("Failed to download update");
+
+    // the progress reported meanwhile is replaced by the completion
+    assert_eq!(ongoing.iter().map(|mock| mock.hits()).sum::<usize>(), 1);
+    assert_eq!(done.hits(), 1);
+}
+
+#[tokio::test]
+async fn hostile_file_names() {
+    use assert_matches::assert_matches;
+
+    init();
+
+    let server = ServerBuilder::default().build();
+
+    let hostile = [
+        "../../etc/shadow",
+        "/etc/shadow",
+        "..",
+        "dir/file",
+        "file\0",
+    ];
+    for (i, name) in hostile.iter().enumerate() {
+        for (chunk_name, filename) in &[(*name, "test.txt"), ("some-chunk", *name)] {
+            let (client, target) = add_target(&server, &format!("Target{}", i));
+            let deploy = DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
+                .chunk(
+                    ChunkProtocol::BOTH,
+                    "app",
+                    "1.0",
+                    chunk_name,
+                    vec![test_artifact(artifact_path())],
+                )
+                .artifact_filename(&artifact_path(), filename)
+                .build();
+            target.push_deployment(deploy);
+
+            let reply = client.poll().await.expect("poll failed");
+            let update = reply.update().expect("missing update");
+            let update = update.fetch().await.expect("failed to fetch update info");
+            let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+
+            let res = update.download(out_dir.path()).await;
+            assert_matches!(res, Err(Error::InvalidFileName(n)) if n == *name);
+
+            if *chunk_name == "some-chunk" {
+                // files can still be downloaded using their hash as name
+                let options = DownloadOptions::default().file_naming(FileNaming::Sha256);
+                let artifacts = update
+                    .download_with_options(out_dir.path(), &options)
+                    .await
+                    .expect("Failed to download update");
+                assert_eq!(
+                    artifacts[0].file(),
+                    &out_dir
+                        .path()
+                        .join("some-chunk")
+                        .join("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9")
+                );
+                assert!(artifacts[0].file().exists());
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn artifact_size() {
+    use assert_matches::assert_matches;
+
+    init();
+
+    let server = ServerBuilder::default().build();
+
+    // artifacts larger than 4 GiB are supported, the actual file is shorter
+    let large = 5 * 1024 * 1024 * 1024;
+    for (i, (size, resume)) in [(large, false), (20, true), (5, true)].iter().enumerate() {
+        let (client, target) = add_target(&server, &format!("Target{}", i));
+        let deploy = DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
+            .chunk(
+                ChunkProtocol::HTTP,
+                "app",
+                "1.0",
+                "some-chunk",
+                vec![test_artifact(artifact_path())],
+            )
+            .artifact_size(&artifact_path(), *size)
+            .build();
+        target.push_deployment(deploy);
+
+        let reply = client.poll().await.expect("poll failed");
+        let update = reply.update().expect("missing update");
+        let update = update.fetch().await.expect("failed to fetch update info");
+        let chunk = update.chunks().next().unwrap();
+        let art = chunk.artifacts().next().unwrap();
+        assert_eq!(art.size(), *size);
+
+        let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+        let options = DownloadOptions::default().resume(*resume);
+        let res = art.download_with_options(out_dir.path(), &options).await;
+        assert!(!out_dir.path().join("test.txt").exists());
+
+        if *size > 11 {
+            // short read, the partial file is kept to be resumed
+            assert_matches!(res, Err(Error::SizeMismatch { expected, received: 11 }) if expected == *size);
+            assert_eq!(out_dir.path().join("test.txt.part").exists(), *resume);
+        } else {
+            // the download is aborted as soon as it exceeds the announced size
+            assert_matches!(res, Err(Error::SizeMismatch { expected: 5, received }) if received > 5);
+            assert!(!out_dir.path().join("test.txt.part").exists());
+        }
+    }
+}
+
+#[tokio::test]
+async fn concurrent_downloads() {
+    use assert_matches::assert_matches;
+
+    init();
+
+    // replies are held long enough to observe the transfers in flight
+    const DELAY: Duration = Duration::from_secs(3);
+
+    // wait for `cond` to be true, the transfers being started asynchronously
+    async fn wait_for(cond: impl Fn() -> bool) {
+        for _ in 0..200 {
+            if cond() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("condition not reached");
+    }
+
+    let server = ServerBuilder::default().build();
+    // distinct artifacts so each transfer can be tracked
+    let art_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let names: Vec<_> = (0..6).map(|c| format!("artifact-{}.txt", c)).collect();
+    let deployment = |oversized: bool| {
+        let mut deploy =
+            DeploymentBuilder::new("10", Type::Forced, Type::Attempt).download_delay(DELAY);
+        for (c, name) in names.iter().enumerate() {
+            let path = art_dir.path().join(name);
+            std::fs::write(&path, "hello world").unwrap();
+            deploy = deploy.chunk(
+                ChunkProtocol::HTTP,
+                "app",
+                "1.0",
+                &format!("chunk-{}", c),
+                vec![test_artifact(path)],
+            );
+        }
+        if oversized {
+            deploy = deploy.artifact_size(&art_dir.path().join(&names[0]), 5);
+        }
+        deploy.build()
+    };
+
+    // up to 4 transfers are in flight, the others wait for a free slot
+    let (client, target) = add_target(&server, "Target1");
+    target.push_deployment(deployment(false));
+
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+    let in_flight = || names.iter().map(|n| target.download_hits(n)).sum::<usize>();
+
+    let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let options = DownloadOptions::default().max_concurrent_downloads(4);
+    let (res, ()) = tokio::join!(
+        update.download_with_options(out_dir.path(), &options),
+        async {
+            wait_for(|| in_flight() == 4).await;
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            assert_eq!(in_flight(), 4);
+        }
+    );
+
+    // artifacts are returned in order
+    let artifacts = res.expect("Failed to download update");
+    let files: Vec<_> = artifacts.iter().map(|a| a.file().clone()).collect();
+    let expected: Vec<_> = names
+        .iter()
+        .enumerate()
+        .map(|(c, name)| out_dir.path().join(format!("chunk-{}", c)).join(name))
+        .collect();
+    assert_eq!(files, expected);
+    assert!(files.iter().all(|f| f.exists()));
+    assert_eq!(in_flight(), 6);
+
+    // the pending transfers are dropped on the first error
+    let (client, target) = add_target(&server, "Target2");
+    target.push_deployment(deployment(true));
+
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+
+    let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let options = DownloadOptions::default().max_concurrent_downloads(2);
+    let res = update.download_with_options(out_dir.path(), &options).await;
+    assert_matches!(res, Err(Error::SizeMismatch { expected: 5, .. }));
+
+    // give any transfer started after the error the time to reach the server
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    for (c, name) in names.iter().enumerate() {
+        if c >= 2 {
+            assert_eq!(target.download_hits(name), 0);
+        }
+        let dir = out_dir.path().join(format!("chunk-{}", c));
+        assert!(!dir.join(name).exists());
+        assert!(!dir.join(format!("{}.part", name)).exists());
+    }
+}
+
+#[tokio::test]
+async fn download_rate_limit() {
+    init();
+
+    let server = ServerBuilder::default().build();
+    let target = server.add_target("Target1");
+    let client = ClientBuilder::new(
+        &server.base_url(),
+        &server.tenant,
+        &target.name,
+        &target.key,
+    )
+    .download_rate_limit(5)
+    .build()
+    .expect("DDI creation failed");
+    assert_eq!(client.download_rate_limit(), Some(5));
+
+    target.push_deployment(get_deployment(true));
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+    let chunk = update.chunks().next().unwrap();
+    let art = chunk.artifacts().next().unwrap();
+    let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+
+    // the throttling itself is checked by the unit tests of the rate limiter
+    art.download(out_dir.path())
+        .await
+        .expect("failed to download artifact");
+
+    let stream = art
+        .download_stream()
+        .await
+        .expect("failed to get download stream");
+    let data: Vec<Bytes> = stream.try_collect().await.expect("failed to download");
+    assert_eq!(data.concat(), b"hello world");
+
+    // the limit can be removed at runtime
+    client.set_download_rate_limit(None);
+    assert_eq!(client.download_rate_limit(), None);
+    art.download(out_dir.path())
+        .await
+        .expect("failed to download artifact");
+}
+
+#[cfg(feature = "decompress")]
+#[tokio::test]
+async fn decompression() {
+    use hawkbit::ddi::Decompression;
+
+    init();
+
+    let server = ServerBuilder::default().build();
+
+    let data = |name: &str| {
+        let mut path = PathBuf::new();
+        path.push("tests");
+        path.push("data");
+        path.push(name);
+        path
+    };
+    let formats = vec![
+        #[cfg(feature = "decompress-gzip")]
+        (
+            data("test.txt.gz"),
+            "9aeccdfc5d0180e8644356e87d3dab47",
+            "3fd5727e6882f98edfe0a68532790ee27603e4eb",
+            "c3f793750a8efd9f83e1467649467af723369ca88696a62100ec731ea01d1586",
+        ),
+        #[cfg(feature = "decompress-xz")]
+        (
+            data("test.txt.xz"),
+            "6f5b4c713c33947ef8ad3f2ae9fa73dd",
+            "40c25e75a95a03ec5d144bf6c6e2af30a026fa51",
+            "741f1752fd59a4d6813bc8d8ab5259359c700a70031f864a13fd23f35327ae99",
+        ),
+        #[cfg(feature = "decompress-zstd")]
+        (
+            data("test.txt.zst"),
+            "aa854b2707e1ddbbd8c2ad345b5a68f4",
+            "cc589995b63206873d26c9970893a89b27ea95a5",
+            "b51964d0bb982e454743461a34b53d5028b60dbc8a9e41bea72c62daa5011035",
+        ),
+    ];
+
+    for (i, artifact) in formats.into_iter().enumerate() {
+        let path = artifact.0.clone();
+        // the format is detected using the extension, or else the magic bytes
+        for (j, filename) in [None, Some("image")].iter().enumerate() {
+            let (client, target) = add_target(&server, &format!("Target{}-{}", i, j));
+            let mut deploy = DeploymentBuilder::new("10", Type::Forced, Type::Attempt).chunk(
+                ChunkProtocol::HTTP,
+                "app",
+                "1.0",
+                "some-chunk",
+                vec![artifact.clone()],
+            );
+            if let Some(filename) = filename {
+                deploy = deploy.artifact_filename(&path, filename);
+            }
+            target.push_deployment(deploy.build());
+
+            let reply = client.poll().await.expect("poll failed");
+            let update = reply.update().expect("missing update");
+            let update = update.fetch().await.expect("failed to fetch update info");
+            let chunk = update.chunks().next().unwrap();
+            let art = chunk.artifacts().next().unwrap();
+
+            let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+            let options = DownloadOptions::default().decompress(Decompression::Detect);
+            let downloaded = art
+                .download_with_options(out_dir.path(), &options)
+                .await
+                .expect("failed to download artifact");
+            assert_eq!(
+                *downloaded.file(),
+                out_dir.path().join(filename.unwrap_or("test.txt"))
+            );
+            assert_eq!(std::fs::read(downloaded.file()).unwrap(), b"hello world");
+
+            let stream = art
+                .download_stream_decompressed(Decompression::Detect)
+                .await
+                .expect("failed to get download stream");
+            let data: Vec<Bytes> = stream.try_collect().await.expect("failed to download");
+            assert_eq!(data.concat(), b"hello world");
+        }
+    }
+}
+
+#[cfg(all(feature = "decompress-gzip", feature = "hash-digest"))]
+#[tokio::test]
+async fn decompression_wrong_checksums() {
+    use assert_matches::assert_matches;
+    use hawkbit::ddi::{Compression, Decompression, VerificationPolicy};
+
+    init();
+
+    let server = ServerBuilder::default().build();
+    let (client, target) = add_target(&server, "Target1");
+
+    let mut path = PathBuf::new();
+    path.push("tests");
+    path.push("data");
+    path.push("test.txt.gz");
+    let deploy = DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
+        .chunk(
+            ChunkProtocol::HTTP,
+            "app",
+            "1.0",
+            "some-chunk",
+            // checksums of the decompressed data
+            vec![test_artifact(path)],
+        )
+        .build();
+    target.push_deployment(deploy);
+
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+    let chunk = update.chunks().next().unwrap();
+    let art = chunk.artifacts().next().unwrap();
+
+    // checksums are verified on the compressed data
+    let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let options = DownloadOptions::default()
+        .decompress(Decompression::Codec(Compression::Gzip))
+        .verification(VerificationPolicy::AllAvailable);
+    let res = art.download_with_options(out_dir.path(), &options).await;
+    assert_matches!(res, Err(Error::ChecksumError(_)));
+    assert!(!out_dir.path().join("test.txt").exists());
+    assert!(!out_dir.path().join("test.txt.part").exists());
+
+    let stream = art
+        .download_stream_decompressed(Decompression::Codec(Compression::Gzip))
+        .await
+        .expect("failed to get download stream");
+    let end = stream.skip_while(|b| future::ready(b.is_ok())).next().await;
+    assert_matches!(end, Some(Err(Error::ChecksumError(_))));
+}
+
+#[cfg(feature = "signature")]
+#[tokio::test]
+async fn signature() {
+    use assert_matches::assert_matches;
+    use hawkbit::ddi::TrustedKeys;
+
+    init();
+
+    let server = ServerBuilder::default().build();
+
+    let data = |name: &str| {
+        let mut path = PathBuf::new();
+        path.push("tests");
+        path.push("data");
+        path.push(name);
+        path
+    };
+    let artifact = test_artifact(artifact_path());
+    let signature = (
+        data("test.txt.sig"),
+        "3b803edec7108d45ad162aadaa5be4ea",
+        "afc00df98547c3eb6b877d3f27f43057ce0cc54f",
+        "27f818c5a851d4d13ef4bf39f94a48b69c49922e042ed5efc6736cd6cc7cce42",
+    );
+    let trusted = TrustedKeys::new()
+        .add_key(&std::fs::read_to_string(data("minisign.pub")).unwrap())
+        .expect("invalid key");
+    let untrusted = TrustedKeys::new()
+        .add_key("RWShBQcXNHdJJL8b1iqmVCd9Lj1d0N7ty0A4K14kwmPRLK05h73+JDGE")
+        .expect("invalid key");
+
+    // the signature is provided as a sibling artifact, or as a chunk metadata
+    let deployments = vec![
+        DeploymentBuilder::new("10", Type::Forced, Type::Attempt).chunk(
+            ChunkProtocol::HTTP,
+            "app",
+            "1.0",
+            "some-chunk",
+            vec![artifact.clone(), signature],
+        ),
+        DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
+            .chunk(
+                ChunkProtocol::HTTP,
+                "app",
+                "1.0",
+                "some-chunk",
+                vec![artifact.clone()],
+            )
+            .chunk_metadata(
+                "some-chunk",
+                "test.txt.sig",
+                &std::fs::read_to_string(data("test.txt.sig")).unwrap(),
+            ),
+    ];
+
+    for (i, deploy) in deployments.into_iter().enumerate() {
+        let (client, target) = add_target(&server, &format!("Target{}", i));
+        target.push_deployment(deploy.build());
+
+        let reply = client.poll().await.expect("poll failed");
+        let update = reply.update().expect("missing update");
+        let update = update.fetch().await.expect("failed to fetch update info");
+        let chunk = update.chunks().next().unwrap();
+        let art = chunk.artifacts().next().unwrap();
+
+        let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+        let options = DownloadOptions::default().verify_signatures(trusted.clone());
+        let downloaded = chunk
+            .download_with_options(out_dir.path(), &options)
+            .await
+            .expect("failed to download chunk");
+        let signature = downloaded[0].signature().expect("missing signature");
+        assert!(signature.trusted_comment().contains("file:test.txt"));
+        // the signature itself is not signed
+        if let Some(sig) = downloaded.get(1) {
+            assert!(sig.signature().is_none());
+        }
+
+        let stream = art
+            .download_stream_with_signature_check(&trusted)
+            .await
+            .expect("failed to get download stream");
+        let data: Vec<Bytes> = stream.try_collect().await.expect("failed to download");
+        assert_eq!(data.concat(), b"hello world");
+
+        // signatures from untrusted keys are rejected
+        let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+        let options = DownloadOptions::default().verify_signatures(untrusted.clone());
+        let res = art.download_with_options(out_dir.path(), &options).await;
+        assert_matches!(res, Err(Error::InvalidSignature(_)));
+        assert!(!out_dir.path().join("test.txt").exists());
+        assert!(!out_dir.path().join("test.txt.part").exists());
+
+        let stream = art
+            .download_stream_with_signature_check(&untrusted)
+            .await
+            .expect("failed to get download stream");
+        let end = stream.skip_while(|b| future::ready(b.is_ok())).next().await;
+        assert_matches!(end, Some(Err(Error::InvalidSignature(_))));
+    }
+
+    // artifacts without signature are rejected
+    let (client, target) = add_target(&server, "Target-unsigned");
+    target.push_deployment(get_deployment(true));
+
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+    let chunk = update.chunks().next().unwrap();
+    let art = chunk.artifacts().next().unwrap();
+
+    let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let options = DownloadOptions::default().verify_signatures(trusted.clone());
+    let res = art.download_with_options(out_dir.path(), &options).await;
+    assert_matches!(res, Err(Error::MissingSignature(name)) if name == "test.txt");
+    let res = art.download_stream_with_signature_check(&trusted).await;
+    assert!(matches!(res, Err(Error::MissingSignature(_))));
+
+    // oversized signatures are rejected, even if the server announces a small size
+    let sig_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let large_sig = sig_dir.path().join("test.txt.sig");
+    std::fs::write(&large_sig, vec![b'a'; 8192]).unwrap();
+    let (client, target) = add_target(&server, "Target-large-signature");
+    target.push_deployment(
+        DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
+            .chunk(
+                ChunkProtocol::HTTP,
+                "app",
+                "1.0",
+                "some-chunk",
+                vec![artifact, (large_sig.clone(), "badger", "badger", "badger")],
+            )
+            .artifact_size(&large_sig, 100)
+            .build(),
+    );
+
+    let reply = client.poll().await.expect("poll failed");
+    let update = reply.update().expect("missing update");
+    let update = update.fetch().await.expect("failed to fetch update info");
+    let chunk = update.chunks().next().unwrap();
+    let art = chunk.artifacts().next().unwrap();
+
+    let out_dir = TempDir::new("test-hawkbitrs").expect("Failed to create temp dir");
+    let options = DownloadOptions::default().verify_signatures(trusted);
+    let res = art.download_with_options(out_dir.path(), &options).await;
+    assert_matches!(res, Err(Error::SignatureTooLarge(name)) if name == "test.txt.sig");
 }
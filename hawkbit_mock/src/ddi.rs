@@ -22,19 +22,25 @@
 
 // FIXME: set link to hawbit/tests/tests.rs once we have the final public repo
 
+use std::collections::HashMap;
 use std::rc::Rc;
+use std::time::Duration;
 use std::{
     cell::{Cell, RefCell},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use httpmock::{
     Method::{GET, POST, PUT},
-    MockRef, MockRefExt, MockServer,
+    MockRef, MockRefExt, MockServer, When,
 };
+use regex::Regex;
 use serde_json::{json, Map, Value};
 
-use hawkbit::ddi::{Execution, Finished, MaintenanceWindow, Type};
+use hawkbit::ddi::{Auth, ConfirmationResponse, Execution, Finished, MaintenanceWindow, Type};
+
+#[cfg(feature = "tls")]
+use crate::tls::{TlsConfig, TlsProxy};
 
 /// Builder of [`Server`].
 ///
@@ -47,12 +53,16 @@ use hawkbit::ddi::{Execution, Finished, MaintenanceWindow, Type};
 /// ```
 pub struct ServerBuilder {
     tenant: String,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
 }
 
 impl Default for ServerBuilder {
     fn default() -> Self {
         Self {
             tenant: "DEFAULT".into(),
+            #[cfg(feature = "tls")]
+            tls: None,
         }
     }
 }
@@ -65,11 +75,43 @@ impl ServerBuilder {
         builder
     }
 
+    /// Serve the API over HTTPS, using the PEM encoded `certificate` and `key`.
+    ///
+    /// If `client_ca` is set, clients have to authenticate using a certificate
+    /// signed by this PEM encoded certificate authority.
+    #[cfg(feature = "tls")]
+    pub fn tls(self, certificate: &[u8], key: &[u8], client_ca: Option<&[u8]>) -> Self {
+        let mut builder = self;
+        builder.tls = Some(TlsConfig {
+            certificate: certificate.to_vec(),
+            key: key.to_vec(),
+            client_ca: client_ca.map(|ca| ca.to_vec()),
+        });
+        builder
+    }
+
     /// Create the [`Server`].
     pub fn build(self) -> Server {
+        let server = MockServer::start();
+
+        #[cfg(feature = "tls")]
+        if let Some(tls) = self.tls {
+            let proxy = TlsProxy::start(&tls, *server.address());
+
+            return Server {
+                base_url: format!("https://{}", proxy.address()),
+                server: Rc::new(server),
+                tenant: self.tenant,
+                _tls: Some(proxy),
+            };
+        }
+
         Server {
-            server: Rc::new(MockServer::start()),
+            base_url: server.base_url(),
+            server: Rc::new(server),
             tenant: self.tenant,
+            #[cfg(feature = "tls")]
+            _tls: None,
         }
     }
 }
@@ -78,18 +120,40 @@ impl ServerBuilder {
 pub struct Server {
     /// The tenant of the server.
     pub tenant: String,
+    base_url: String,
     server: Rc<MockServer>,
+    #[cfg(feature = "tls")]
+    _tls: Option<TlsProxy>,
 }
 
 impl Server {
     /// The base URL of the server, such as `http://my-server.com:8080`
     pub fn base_url(&self) -> String {
-        self.server.base_url()
+        self.base_url.clone()
     }
 
     /// Add a new target named `name` to the server.
+    ///
+    /// The target is required to authenticate using its secret token, see [`Target::key`].
     pub fn add_target(&self, name: &str) -> Target {
-        Target::new(name, &self.server, &self.tenant)
+        let key = format!("Key{}", name);
+        self.add_target_with_auth(name, Auth::TargetToken(key))
+    }
+
+    /// Add a new target named `name` to the server, requiring it to authenticate using `auth`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hawkbit::ddi::Auth;
+    /// use hawkbit_mock::ddi::ServerBuilder;
+    ///
+    /// let server = ServerBuilder::default().build();
+    /// let target = server.add_target_with_auth("Target1", Auth::GatewayToken("Gateway".to_string()));
+    /// let anonymous = server.add_target_with_auth("Target2", Auth::None);
+    /// ```
+    pub fn add_target_with_auth(&self, name: &str, auth: Auth) -> Target {
+        Target::new(name, auth, &self.server, &self.base_url, &self.tenant)
     }
 }
 
@@ -98,29 +162,53 @@ pub struct Target {
     /// The name of the target.
     pub name: String,
     /// The secret authentification token used to identify the target on the server.
+    ///
+    /// Only required by the server if the target uses [`Auth::TargetToken`].
     pub key: String,
+    /// The authentication method required by the server for this target.
+    pub auth: Auth,
+    authorization: Option<String>,
     server: Rc<MockServer>,
+    base_url: String,
     tenant: String,
     poll: Cell<usize>,
     config_data: RefCell<Option<PendingAction>>,
     deployment: RefCell<Option<PendingAction>>,
     cancel_action: RefCell<Option<PendingAction>>,
+    confirmation: RefCell<Option<PendingAction>>,
+    installed: RefCell<Option<PendingAction>>,
+    // mocks serving the artifacts, by file name
+    downloads: RefCell<HashMap<String, Vec<usize>>>,
 }
 
 impl Target {
-    fn new(name: &str, server: &Rc<MockServer>, tenant: &str) -> Self {
-        let key = format!("Key{}", name);
+    fn new(name: &str, auth: Auth, server: &Rc<MockServer>, base_url: &str, tenant: &str) -> Self {
+        let key = match &auth {
+            Auth::TargetToken(key) => key.clone(),
+            _ => format!("Key{}", name),
+        };
+        let authorization = match &auth {
+            Auth::TargetToken(token) => Some(format!("TargetToken {}", token)),
+            Auth::GatewayToken(token) => Some(format!("GatewayToken {}", token)),
+            Auth::None => None,
+        };
 
-        let poll = Self::create_poll(server, tenant, name, &key, None, None, None);
+        let poll = Self::create_poll(server, tenant, name, authorization.as_deref(), &[]);
         Target {
             name: name.to_string(),
             key,
+            auth,
+            authorization,
             server: server.clone(),
+            base_url: base_url.to_string(),
             tenant: tenant.to_string(),
             poll: Cell::new(poll),
             config_data: RefCell::new(None),
             deployment: RefCell::new(None),
             cancel_action: RefCell::new(None),
+            confirmation: RefCell::new(None),
+            installed: RefCell::new(None),
+            downloads: RefCell::new(HashMap::new()),
         }
     }
 
@@ -128,21 +216,16 @@ impl Target {
         server: &MockServer,
         tenant: &str,
         name: &str,
-        key: &str,
-        expected_config_data: Option<&PendingAction>,
-        deployment: Option<&PendingAction>,
-        cancel_action: Option<&PendingAction>,
+        authorization: Option<&str>,
+        pending: &[(&str, Option<&PendingAction>)],
     ) -> usize {
         let mut links = Map::new();
 
-        if let Some(pending) = expected_config_data {
-            links.insert("configData".into(), json!({ "href": pending.path }));
-        }
-        if let Some(pending) = deployment {
-            links.insert("deploymentBase".into(), json!({ "href": pending.path }));
-        }
-        if let Some(pending) = cancel_action {
-            links.insert("cancelAction".into(), json!({ "href": pending.path }));
+        // link to each pending action, if any
+        for (name, pending) in pending {
+            if let Some(pending) = pending {
+                links.insert(name.to_string(), json!({ "href": pending.path }));
+            }
         }
 
         let response = json!({
@@ -155,9 +238,9 @@ impl Target {
         });
 
         let mock = server.mock(|when, then| {
-            when.method(GET)
-                .path(format!("/{}/controller/v1/{}", tenant, name))
-                .header("Authorization", &format!("TargetToken {}", key));
+            authorized(when, authorization)
+                .method(GET)
+                .path(format!("/{}/controller/v1/{}", tenant, name));
 
             then.status(200)
                 .header("Content-Type", "application/json")
@@ -172,10 +255,14 @@ impl Target {
             &self.server,
             &self.tenant,
             &self.name,
-            &self.key,
-            self.config_data.borrow().as_ref(),
-            self.deployment.borrow().as_ref(),
-            self.cancel_action.borrow().as_ref(),
+            self.authorization.as_deref(),
+            &[
+                ("configData", self.config_data.borrow().as_ref()),
+                ("deploymentBase", self.deployment.borrow().as_ref()),
+                ("cancelAction", self.cancel_action.borrow().as_ref()),
+                ("confirmationBase", self.confirmation.borrow().as_ref()),
+                ("installedBase", self.installed.borrow().as_ref()),
+            ],
         ));
 
         let mut old = MockRef::new(old, &self.server);
@@ -213,15 +300,13 @@ impl Target {
     /// //assert_eq!(target.config_data_hits(), 1);
     /// ```
     pub fn request_config(&self, expected_config_data: Value) {
-        let config_path = self
-            .server
-            .url(format!("/DEFAULT/controller/v1/{}/configData", self.name));
+        let config_path = self.url(&format!("/DEFAULT/controller/v1/{}/configData", self.name));
 
         let config_data = self.server.mock(|when, then| {
-            when.method(PUT)
+            self.authorized(when)
+                .method(PUT)
                 .path(format!("/DEFAULT/controller/v1/{}/configData", self.name))
                 .header("Content-Type", "application/json")
-                .header("Authorization", &format!("TargetToken {}", self.key))
                 .json_body(expected_config_data);
 
             then.status(200);
@@ -230,7 +315,7 @@ impl Target {
         self.config_data.replace(Some(PendingAction {
             server: self.server.clone(),
             path: config_path,
-            mock: config_data.id(),
+            mocks: vec![config_data.id()],
         }));
 
         self.update_poll();
@@ -271,50 +356,125 @@ impl Target {
     /// //assert_eq!(target.deployment_hits(), 1);
     /// ```
     pub fn push_deployment(&self, deploy: Deployment) {
-        let deploy_path = self.server.url(format!(
+        let deploy_path = self.url(&format!(
             "/DEFAULT/controller/v1/{}/deploymentBase/{}",
             self.name, deploy.id
         ));
 
-        let base_url = self.server.url("/download");
+        let base_url = self.url("/download");
         let response = deploy.json(&base_url);
+        let mut mocks = Vec::new();
+
+        // the action history is only provided if requested,
+        // this mock has to be created first as the oldest matching mock is used
+        if let Some(history) = deploy.action_history_json() {
+            let mut response = response.clone();
+            response["actionHistory"] = history;
+
+            let history_mock = self.server.mock(|when, then| {
+                self.authorized(when)
+                    .method(GET)
+                    .path(format!(
+                        "/DEFAULT/controller/v1/{}/deploymentBase/{}",
+                        self.name, deploy.id
+                    ))
+                    .query_param_exists("actionHistory");
+
+                then.status(200)
+                    .header("Content-Type", "application/json")
+                    .json_body(response);
+            });
+            mocks.push(history_mock.id());
+        }
 
         let deploy_mock = self.server.mock(|when, then| {
-            when.method(GET)
-                .path(format!(
-                    "/DEFAULT/controller/v1/{}/deploymentBase/{}",
-                    self.name, deploy.id
-                ))
-                .header("Authorization", &format!("TargetToken {}", self.key));
+            self.authorized(when).method(GET).path(format!(
+                "/DEFAULT/controller/v1/{}/deploymentBase/{}",
+                self.name, deploy.id
+            ));
 
             then.status(200)
                 .header("Content-Type", "application/json")
                 .json_body(response);
         });
+        mocks.push(deploy_mock.id());
+
+        self.serve_artifacts(&deploy);
+
+        self.deployment.replace(Some(PendingAction {
+            server: self.server.clone(),
+            path: deploy_path,
+            mocks,
+        }));
+
+        self.update_poll();
+    }
 
-        // Serve the artifacts
+    /// Serve the artifacts of `deploy`, and their md5sum files.
+    fn serve_artifacts(&self, deploy: &Deployment) {
         for chunk in deploy.chunks.iter() {
-            for (artifact, _md5, _sha1, _sha256) in chunk.artifacts.iter() {
+            for (artifact, md5, _sha1, _sha256) in chunk.artifacts.iter() {
                 let file_name = artifact.file_name().unwrap().to_str().unwrap();
                 let path = format!("/download/{}", file_name);
 
+                // md5sum file, in the same format as hawkBit
+                let filename = deploy
+                    .overrides
+                    .filenames
+                    .get(artifact)
+                    .map_or(file_name, |f| f.as_str());
                 self.server.mock(|when, then| {
-                    when.method(GET)
-                        .path(path)
-                        .header("Authorization", &format!("TargetToken {}", self.key));
+                    self.authorized(when)
+                        .method(GET)
+                        .path(format!("{}.MD5SUM", path));
+
+                    then.status(200).body(format!("{}  {}\n", md5, filename));
+                });
 
-                    then.status(200).body_from_file(artifact.to_str().unwrap());
+                self.serve_ranges(&path, artifact, &deploy.range_requests);
+
+                let mock = self.server.mock(|when, then| {
+                    self.authorized(when).method(GET).path(path);
+
+                    let then = then.status(200).body_from_file(artifact.to_str().unwrap());
+                    if let Some(delay) = deploy.download_delay {
+                        then.delay(delay);
+                    }
                 });
+                self.downloads
+                    .borrow_mut()
+                    .entry(file_name.to_string())
+                    .or_default()
+                    .push(mock.id());
             }
         }
+    }
 
-        self.deployment.replace(Some(PendingAction {
-            server: self.server.clone(),
-            path: deploy_path,
-            mock: deploy_mock.id(),
-        }));
-
-        self.update_poll();
+    /// Serve the ranges of `artifact` starting at `offsets` at `path`, as httpmock
+    /// cannot compute the replies to range requests dynamically.
+    /// Must be called before serving the whole file as the oldest matching mock is used.
+    fn serve_ranges(&self, path: &str, artifact: &Path, offsets: &[usize]) {
+        if offsets.is_empty() {
+            return;
+        }
+        let data = std::fs::read(artifact).unwrap();
+        let len = data.len();
+
+        for &start in offsets.iter().filter(|&&start| start < len) {
+            self.server.mock(|when, then| {
+                self.authorized(when)
+                    .method(GET)
+                    .path(path)
+                    .header("Range", &format!("bytes={}-", start));
+
+                then.status(206)
+                    .header(
+                        "Content-Range",
+                        &format!("bytes {}-{}/{}", start, len - 1, len),
+                    )
+                    .body(&data[start..]);
+            });
+        }
     }
 
     /// Configure the server to expect deployment feedback from the target.
@@ -375,12 +535,12 @@ impl Target {
                 }),
             };
 
-            when.method(POST)
+            self.authorized(when)
+                .method(POST)
                 .path(format!(
                     "/{}/controller/v1/{}/deploymentBase/{}/feedback",
                     self.tenant, self.name, deployment_id
                 ))
-                .header("Authorization", &format!("TargetToken {}", self.key))
                 .header("Content-Type", "application/json")
                 .json_body(expected);
 
@@ -388,6 +548,70 @@ impl Target {
         })
     }
 
+    /// Configure the server to expect deployment `feedback` from the target, including
+    /// its optional progress, status code and timestamp.
+    /// One can then check the feedback has actually been received using
+    /// `hits()` on the returned object.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hawkbit_mock::ddi::{DeploymentFeedback, ServerBuilder};
+    /// use hawkbit::ddi::{Execution, Finished};
+    /// use serde_json::json;
+    ///
+    /// let server = ServerBuilder::default().build();
+    /// let target = server.add_target("Target1");
+    /// let feedback = DeploymentFeedback::new(Execution::Proceeding, Finished::None)
+    ///     .progress(json!({"cnt": 2, "of": 5}))
+    ///     .code(12)
+    ///     .details(vec!["Installing"]);
+    /// let mut mock = target.expect_deployment_feedback_with("10", feedback);
+    /// assert_eq!(mock.hits(), 0);
+    /// ```
+    pub fn expect_deployment_feedback_with(
+        &self,
+        deployment_id: &str,
+        feedback: DeploymentFeedback,
+    ) -> MockRef<'_> {
+        self.server.mock(|when, then| {
+            let mut result = Map::new();
+            if let Some(progress) = feedback.progress {
+                result.insert("progress".to_string(), progress);
+            }
+            result.insert("finished".to_string(), json!(feedback.finished));
+
+            let mut status = Map::new();
+            status.insert("result".to_string(), Value::Object(result));
+            status.insert("execution".to_string(), json!(feedback.execution));
+            if let Some(code) = feedback.code {
+                status.insert("code".to_string(), json!(code));
+            }
+            status.insert("details".to_string(), json!(feedback.details));
+
+            let mut expected = Map::new();
+            expected.insert("id".to_string(), json!(deployment_id));
+            if let Some(timestamp) = feedback.timestamp {
+                expected.insert("timestamp".to_string(), json!(timestamp));
+            }
+            expected.insert("status".to_string(), Value::Object(status));
+
+            self.authorized(when)
+                .method(POST)
+                .path(format!(
+                    "/{}/controller/v1/{}/deploymentBase/{}/feedback",
+                    self.tenant, self.name, deployment_id
+                ))
+                .header("Content-Type", "application/json")
+                .json_body(Value::Object(expected));
+
+            let then = then.status(200);
+            if let Some(delay) = feedback.reply_delay {
+                then.delay(delay);
+            }
+        })
+    }
+
     /// Push a cancel action update to the target.
     /// One can then use [`Target::cancel_action_hits`] to check that the client
     /// fetched the details about the cancel action.
@@ -405,7 +629,7 @@ impl Target {
     /// //assert_eq!(target.cancel_action_hits(), 1);
     /// ```
     pub fn cancel_action(&self, id: &str) {
-        let cancel_path = self.server.url(format!(
+        let cancel_path = self.url(&format!(
             "/DEFAULT/controller/v1/{}/cancelAction/{}",
             self.name, id
         ));
@@ -418,12 +642,10 @@ impl Target {
         });
 
         let cancel_mock = self.server.mock(|when, then| {
-            when.method(GET)
-                .path(format!(
-                    "/DEFAULT/controller/v1/{}/cancelAction/{}",
-                    self.name, id
-                ))
-                .header("Authorization", &format!("TargetToken {}", self.key));
+            self.authorized(when).method(GET).path(format!(
+                "/DEFAULT/controller/v1/{}/cancelAction/{}",
+                self.name, id
+            ));
 
             then.status(200)
                 .header("Content-Type", "application/json")
@@ -433,7 +655,7 @@ impl Target {
         self.cancel_action.replace(Some(PendingAction {
             server: self.server.clone(),
             path: cancel_path,
-            mock: cancel_mock.id(),
+            mocks: vec![cancel_mock.id()],
         }));
 
         self.update_poll();
@@ -484,12 +706,12 @@ impl Target {
                 },
             });
 
-            when.method(POST)
+            self.authorized(when)
+                .method(POST)
                 .path(format!(
                     "/{}/controller/v1/{}/cancelAction/{}/feedback",
                     self.tenant, self.name, cancel_id
                 ))
-                .header("Authorization", &format!("TargetToken {}", self.key))
                 .header("Content-Type", "application/json")
                 .json_body(expected);
 
@@ -497,6 +719,391 @@ impl Target {
         })
     }
 
+    fn authorized(&self, when: When) -> When {
+        authorized(when, self.authorization.as_deref())
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Make the server reply to the requests of the target with the error `status`,
+    /// and `body` as JSON content if any, until the returned mock is deleted.
+    ///
+    /// This applies to polling and to all the target endpoints configured after this call.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hawkbit_mock::ddi::ServerBuilder;
+    /// use serde_json::json;
+    ///
+    /// let server = ServerBuilder::default().build();
+    /// let target = server.add_target("Target1");
+    /// let mut mock = target.inject_error(
+    ///         401,
+    ///         Some(json!({
+    ///             "errorCode": "hawkbit.server.error.unauthorized",
+    ///             "message": "Bad token",
+    ///         })),
+    ///     );
+    ///
+    /// // Client polls and gets an error
+    /// //assert_eq!(mock.hits(), 1);
+    ///
+    /// mock.delete();
+    /// ```
+    pub fn inject_error(&self, status: u16, body: Option<Value>) -> MockRef<'_> {
+        self.create_error(status, body, None)
+    }
+
+    /// Make the server reply to the requests of the target with the error `status`
+    /// and asking the client to retry after `retry_after` seconds using the `Retry-After` header,
+    /// until the returned mock is deleted.
+    ///
+    /// Same as [`Target::inject_error`] otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hawkbit_mock::ddi::ServerBuilder;
+    ///
+    /// let server = ServerBuilder::default().build();
+    /// let target = server.add_target("Target1");
+    /// let mut mock = target.inject_retry_after(429, 2);
+    ///
+    /// // Client polls and retries after 2 seconds
+    /// //assert_eq!(mock.hits(), 1);
+    ///
+    /// mock.delete();
+    /// ```
+    pub fn inject_retry_after(&self, status: u16, retry_after: u64) -> MockRef<'_> {
+        self.create_error(status, None, Some(retry_after))
+    }
+
+    fn create_error(
+        &self,
+        status: u16,
+        body: Option<Value>,
+        retry_after: Option<u64>,
+    ) -> MockRef<'_> {
+        let path = Regex::new(&format!(
+            "^/{}/controller/v1/{}(/.*)?$",
+            regex::escape(&self.tenant),
+            regex::escape(&self.name)
+        ))
+        .unwrap();
+
+        let mock = self.server.mock(|when, then| {
+            self.authorized(when).path_matches(path);
+
+            let mut then = then.status(status);
+            if let Some(retry_after) = retry_after {
+                then = then.header("Retry-After", &retry_after.to_string());
+            }
+            if let Some(body) = body {
+                then.header("Content-Type", "application/json")
+                    .json_body(body);
+            }
+        });
+
+        // Mocks are matched in their creation order so re-create the poll one
+        // for the error to take precedence.
+        self.update_poll();
+
+        mock
+    }
+
+    /// Request the target to confirm the deployment `deploy` before it starts.
+    /// One can then use [`Target::confirmation_hits`] to check that the client
+    /// retrieved the details of the action to confirm.
+    ///
+    /// The deployment itself has to be pushed using [`Target::push_deployment`]
+    /// once confirmed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use hawkbit_mock::ddi::{ChunkProtocol, ServerBuilder, DeploymentBuilder};
+    /// use hawkbit::ddi::Type;
+    ///
+    /// let server = ServerBuilder::default().build();
+    /// let target = server.add_target("Target1");
+    ///
+    /// let deployment = DeploymentBuilder::new("10", Type::Forced, Type::Attempt)
+    ///    .chunk(
+    ///       ChunkProtocol::BOTH,
+    ///       "app",
+    ///       "1.0",
+    ///        "some-chunk",
+    ///        vec![(
+    ///            Path::new("README.md").to_path_buf(),
+    ///            "42cf69051362d8fa2883cc9b56799fa4",
+    ///            "16da060b7ff443a6b3a7662ad21a9b3023c12627",
+    ///            "5010fbc2769bfc655d15aa9a883703d5b19a320732d37f70703ab3e3b416a602",
+    ///        )],
+    ///   )
+    ///    .build();
+    /// target.request_confirmation(deployment);
+    ///
+    /// // Client fetches the details of the action to confirm
+    /// //assert_eq!(target.confirmation_hits(), 1);
+    /// ```
+    pub fn request_confirmation(&self, deploy: Deployment) {
+        let confirmation_path = self.url(&format!(
+            "/{}/controller/v1/{}/confirmationBase/{}",
+            self.tenant, self.name, deploy.id
+        ));
+
+        let base_url = self.url("/download");
+        let mut response = deploy.json(&base_url);
+        let details = response.as_object_mut().unwrap().remove("deployment");
+        response["confirmation"] = details.unwrap();
+        if let Some(history) = deploy.action_history_json() {
+            response["actionHistory"] = history;
+        }
+
+        let confirmation_mock = self.server.mock(|when, then| {
+            self.authorized(when).method(GET).path(format!(
+                "/{}/controller/v1/{}/confirmationBase/{}",
+                self.tenant, self.name, deploy.id
+            ));
+
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(response);
+        });
+
+        self.serve_artifacts(&deploy);
+
+        self.confirmation.replace(Some(PendingAction {
+            server: self.server.clone(),
+            path: confirmation_path,
+            mocks: vec![confirmation_mock.id()],
+        }));
+
+        self.update_poll();
+    }
+
+    /// Set `deploy` as the last action installed on the target.
+    /// One can then use [`Target::installed_hits`] to check that the client
+    /// retrieved the details of the installed action.
+    pub fn set_installed(&self, deploy: Deployment) {
+        let installed_path = self.url(&format!(
+            "/{}/controller/v1/{}/installedBase/{}",
+            self.tenant, self.name, deploy.id
+        ));
+
+        let base_url = self.url("/download");
+        let response = deploy.json(&base_url);
+
+        let installed_mock = self.server.mock(|when, then| {
+            self.authorized(when).method(GET).path(format!(
+                "/{}/controller/v1/{}/installedBase/{}",
+                self.tenant, self.name, deploy.id
+            ));
+
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(response);
+        });
+
+        self.serve_artifacts(&deploy);
+
+        self.installed.replace(Some(PendingAction {
+            server: self.server.clone(),
+            path: installed_path,
+            mocks: vec![installed_mock.id()],
+        }));
+
+        self.update_poll();
+    }
+
+    /// Serve the artifacts of the software modules of `deploy`, without any pending action.
+    /// Only the chunks whose id has been set using [`DeploymentBuilder::chunk_id`] are served.
+    pub fn add_software_modules(&self, deploy: Deployment) {
+        let base_url = self.url("/download");
+
+        for chunk in deploy.chunks.iter() {
+            let id = match chunk.id {
+                Some(id) => id,
+                None => continue,
+            };
+            let response = chunk.artifacts_json(&base_url, &deploy.overrides);
+
+            self.server.mock(|when, then| {
+                self.authorized(when).method(GET).path(format!(
+                    "/{}/controller/v1/{}/softwaremodules/{}/artifacts",
+                    self.tenant, self.name, id
+                ));
+
+                then.status(200)
+                    .header("Content-Type", "application/json")
+                    .json_body(response);
+            });
+        }
+
+        self.serve_artifacts(&deploy);
+    }
+
+    /// Configure the server to expect the target to confirm or deny the action `action_id`.
+    /// One can then check the feedback has actually been received using
+    /// `hits()` on the returned object.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hawkbit_mock::ddi::ServerBuilder;
+    /// use hawkbit::ddi::ConfirmationResponse;
+    ///
+    /// let server = ServerBuilder::default().build();
+    /// let target = server.add_target("Target1");
+    /// let mut mock = target.expect_confirmation_feedback(
+    ///         "10",
+    ///         ConfirmationResponse::Confirmed,
+    ///         Some(12),
+    ///         vec!["Accepted by the user"],
+    ///     );
+    /// assert_eq!(mock.hits(), 0);
+    ///
+    /// //Client send the feedback
+    /// //assert_eq!(mock.hits(), 1);
+    /// ```
+    pub fn expect_confirmation_feedback(
+        &self,
+        action_id: &str,
+        response: ConfirmationResponse,
+        code: Option<i32>,
+        details: Vec<&str>,
+    ) -> MockRef<'_> {
+        self.server.mock(|when, then| {
+            let expected = match code {
+                Some(code) => json!({
+                    "confirmation": response,
+                    "code": code,
+                    "details": details,
+                }),
+                None => json!({
+                    "confirmation": response,
+                    "details": details,
+                }),
+            };
+
+            self.authorized(when)
+                .method(POST)
+                .path(format!(
+                    "/{}/controller/v1/{}/confirmationBase/{}/feedback",
+                    self.tenant, self.name, action_id
+                ))
+                .header("Content-Type", "application/json")
+                .json_body(expected);
+
+            then.status(200);
+        })
+    }
+
+    /// Configure the server to expect the target to activate the auto-confirmation
+    /// of its actions, using `initiator` and `remark`.
+    /// One can then check the request has actually been received using
+    /// `hits()` on the returned object.
+    pub fn expect_auto_confirm_activation(
+        &self,
+        initiator: Option<&str>,
+        remark: Option<&str>,
+    ) -> MockRef<'_> {
+        self.server.mock(|when, then| {
+            let mut expected = Map::new();
+            if let Some(initiator) = initiator {
+                expected.insert("initiator".into(), json!(initiator));
+            }
+            if let Some(remark) = remark {
+                expected.insert("remark".into(), json!(remark));
+            }
+
+            self.authorized(when)
+                .method(POST)
+                .path(format!(
+                    "/{}/controller/v1/{}/confirmationBase/activateAutoConfirm",
+                    self.tenant, self.name
+                ))
+                .header("Content-Type", "application/json")
+                .json_body(Value::Object(expected));
+
+            then.status(200);
+        })
+    }
+
+    /// Configure the server to expect the target to deactivate the auto-confirmation
+    /// of its actions.
+    /// One can then check the request has actually been received using
+    /// `hits()` on the returned object.
+    pub fn expect_auto_confirm_deactivation(&self) -> MockRef<'_> {
+        self.server.mock(|when, then| {
+            self.authorized(when).method(POST).path(format!(
+                "/{}/controller/v1/{}/confirmationBase/deactivateAutoConfirm",
+                self.tenant, self.name
+            ));
+
+            then.status(200);
+        })
+    }
+
+    /// Configure the server to reply with the state of the auto-confirmation of the
+    /// target, activated by `initiator` if `active`.
+    /// One can then check the state has actually been fetched using
+    /// `hits()` on the returned object.
+    pub fn expect_auto_confirm_state(
+        &self,
+        active: bool,
+        initiator: Option<&str>,
+        remark: Option<&str>,
+    ) -> MockRef<'_> {
+        let mut state = Map::new();
+        state.insert("active".into(), json!(active));
+        if let Some(initiator) = initiator {
+            state.insert("initiator".into(), json!(initiator));
+        }
+        if let Some(remark) = remark {
+            state.insert("remark".into(), json!(remark));
+        }
+        let path = format!(
+            "/{}/controller/v1/{}/confirmationBase",
+            self.tenant, self.name
+        );
+        let response = json!({
+            "autoConfirm": state,
+            "_links": {
+                "activateAutoConfirm": {
+                    "href": self.url(&format!("{}/activateAutoConfirm", path)),
+                },
+                "deactivateAutoConfirm": {
+                    "href": self.url(&format!("{}/deactivateAutoConfirm", path)),
+                },
+            },
+        });
+
+        self.server.mock(|when, then| {
+            self.authorized(when).method(GET).path(path);
+
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(response);
+        })
+    }
+
+    /// Return the number of times the artifact `file_name` has been requested by the client,
+    /// including the requests still waiting for their reply, see
+    /// [`DeploymentBuilder::download_delay`].
+    pub fn download_hits(&self, file_name: &str) -> usize {
+        self.downloads.borrow().get(file_name).map_or(0, |mocks| {
+            mocks
+                .iter()
+                .map(|id| MockRef::new(*id, &self.server).hits())
+                .sum()
+        })
+    }
+
     /// Return the number of times the poll API has been called by the client.
     pub fn poll_hits(&self) -> usize {
         let mock = MockRef::new(self.poll.get(), &self.server);
@@ -505,39 +1112,125 @@ impl Target {
 
     /// Return the number of times the target configuration has been uploaded by the client.
     pub fn config_data_hits(&self) -> usize {
-        self.config_data.borrow().as_ref().map_or(0, |m| {
-            let mock = MockRef::new(m.mock, &self.server);
-            mock.hits()
-        })
+        self.config_data.borrow().as_ref().map_or(0, |m| m.hits())
     }
 
     /// Return the number of times the deployment details have been fetched by the client.
     pub fn deployment_hits(&self) -> usize {
-        self.deployment.borrow().as_ref().map_or(0, |m| {
-            let mock = MockRef::new(m.mock, &self.server);
-            mock.hits()
-        })
+        self.deployment.borrow().as_ref().map_or(0, |m| m.hits())
+    }
+
+    /// Return the number of times the details of the action to confirm have been
+    /// fetched by the client.
+    pub fn confirmation_hits(&self) -> usize {
+        self.confirmation.borrow().as_ref().map_or(0, |m| m.hits())
+    }
+
+    /// Return the number of times the details of the installed action have been
+    /// fetched by the client.
+    pub fn installed_hits(&self) -> usize {
+        self.installed.borrow().as_ref().map_or(0, |m| m.hits())
     }
 
     /// Return the number of times the cancel action URL has been fetched by the client.
     pub fn cancel_action_hits(&self) -> usize {
-        self.cancel_action.borrow().as_ref().map_or(0, |m| {
-            let mock = MockRef::new(m.mock, &self.server);
-            mock.hits()
-        })
+        self.cancel_action.borrow().as_ref().map_or(0, |m| m.hits())
+    }
+}
+
+/// Require the request to be authenticated using `authorization`, if any.
+fn authorized(when: When, authorization: Option<&str>) -> When {
+    match authorization {
+        Some(authorization) => when.header("Authorization", authorization),
+        None => when,
     }
 }
 
 struct PendingAction {
     server: Rc<MockServer>,
-    mock: usize,
+    // all the mocks replying to the action, depending on the request
+    mocks: Vec<usize>,
     path: String,
 }
 
+impl PendingAction {
+    fn hits(&self) -> usize {
+        self.mocks
+            .iter()
+            .map(|id| MockRef::new(*id, &self.server).hits())
+            .sum()
+    }
+}
+
 impl Drop for PendingAction {
     fn drop(&mut self) {
-        let mut mock = MockRef::new(self.mock, &self.server);
-        mock.delete();
+        for id in self.mocks.iter() {
+            let mut mock = MockRef::new(*id, &self.server);
+            mock.delete();
+        }
+    }
+}
+
+/// Deployment feedback expected from the target, see
+/// [`Target::expect_deployment_feedback_with`].
+pub struct DeploymentFeedback {
+    execution: Execution,
+    finished: Finished,
+    progress: Option<Value>,
+    code: Option<i32>,
+    timestamp: Option<u64>,
+    details: Vec<String>,
+    reply_delay: Option<Duration>,
+}
+
+impl DeploymentFeedback {
+    /// Expect feedback reporting `execution` and `finished`, without any details.
+    pub fn new(execution: Execution, finished: Finished) -> Self {
+        Self {
+            execution,
+            finished,
+            progress: None,
+            code: None,
+            timestamp: None,
+            details: Vec::new(),
+            reply_delay: None,
+        }
+    }
+
+    /// Expect the feedback to report `progress`.
+    pub fn progress(self, progress: Value) -> Self {
+        let mut feedback = self;
+        feedback.progress = Some(progress);
+        feedback
+    }
+
+    /// Expect the feedback to report the status `code`.
+    pub fn code(self, code: i32) -> Self {
+        let mut feedback = self;
+        feedback.code = Some(code);
+        feedback
+    }
+
+    /// Expect the feedback to be timestamped at `timestamp`, in milliseconds since the epoch.
+    pub fn timestamp(self, timestamp: u64) -> Self {
+        let mut feedback = self;
+        feedback.timestamp = Some(timestamp);
+        feedback
+    }
+
+    /// Expect the feedback to report `details`.
+    pub fn details(self, details: Vec<&str>) -> Self {
+        let mut feedback = self;
+        feedback.details = details.iter().map(|m| m.to_string()).collect();
+        feedback
+    }
+
+    /// Wait for `delay` before replying to the feedback, such as to test that clients
+    /// are not slowed down by the server.
+    pub fn reply_delay(self, delay: Duration) -> Self {
+        let mut feedback = self;
+        feedback.reply_delay = Some(delay);
+        feedback
     }
 }
 
@@ -548,6 +1241,19 @@ pub struct DeploymentBuilder {
     update_type: Type,
     maintenance_window: Option<MaintenanceWindow>,
     chunks: Vec<Chunk>,
+    range_requests: Vec<usize>,
+    download_delay: Option<Duration>,
+    overrides: ArtifactOverrides,
+    action_history: Option<(String, Vec<String>)>,
+}
+
+/// Artifact properties announced by the server which differ from the actual files,
+/// keyed by the local path of the artifact.
+#[derive(Default)]
+struct ArtifactOverrides {
+    filenames: HashMap<PathBuf, String>,
+    sizes: HashMap<PathBuf, u64>,
+    https_base_url: Option<String>,
 }
 
 /// A pending deployment update pushed to the target.
@@ -558,6 +1264,10 @@ pub struct Deployment {
     update_type: Type,
     maintenance_window: Option<MaintenanceWindow>,
     chunks: Vec<Chunk>,
+    range_requests: Vec<usize>,
+    download_delay: Option<Duration>,
+    overrides: ArtifactOverrides,
+    action_history: Option<(String, Vec<String>)>,
 }
 
 impl DeploymentBuilder {
@@ -569,9 +1279,65 @@ impl DeploymentBuilder {
             update_type,
             maintenance_window: None,
             chunks: Vec::new(),
+            range_requests: Vec::new(),
+            download_delay: None,
+            overrides: ArtifactOverrides::default(),
+            action_history: None,
         }
     }
 
+    /// Announce `filename` as the file name of the artifact at `path`, instead of
+    /// its actual name, such as to test how clients handle hostile names.
+    pub fn artifact_filename(self, path: &Path, filename: &str) -> Self {
+        let mut builder = self;
+        builder
+            .overrides
+            .filenames
+            .insert(path.to_path_buf(), filename.to_string());
+        builder
+    }
+
+    /// Announce `size` as the size of the artifact at `path`, instead of its
+    /// actual size, such as to test how clients handle truncated or inflated downloads.
+    pub fn artifact_size(self, path: &Path, size: u64) -> Self {
+        let mut builder = self;
+        builder.overrides.sizes.insert(path.to_path_buf(), size);
+        builder
+    }
+
+    /// Announce the HTTPS links of the artifacts using `url` as base, instead of
+    /// the mock server, such as to test how clients handle unreachable links.
+    pub fn https_base_url(self, url: &str) -> Self {
+        let mut builder = self;
+        builder.overrides.https_base_url = Some(url.to_string());
+        builder
+    }
+
+    /// Reply to the HTTP `Range` requests starting at one of `offsets` with
+    /// `206 Partial Content`. The whole file is sent for any other request.
+    pub fn range_requests(self, offsets: &[usize]) -> Self {
+        let mut builder = self;
+        builder.range_requests = offsets.to_vec();
+        builder
+    }
+
+    /// Wait for `delay` before replying to the requests downloading the whole artifacts,
+    /// such as to test how many downloads clients run at the same time.
+    pub fn download_delay(self, delay: Duration) -> Self {
+        let mut builder = self;
+        builder.download_delay = Some(delay);
+        builder
+    }
+
+    /// Set the history of the action, with its `status` and the `messages` previously
+    /// sent by the target, provided when requested with the `actionHistory` query parameter.
+    pub fn action_history(self, status: &str, messages: Vec<&str>) -> Self {
+        let mut builder = self;
+        let messages = messages.into_iter().map(|m| m.to_string()).collect();
+        builder.action_history = Some((status.to_string(), messages));
+        builder
+    }
+
     /// Set the maintenance window status of the deployment.
     pub fn maintenance_window(self, maintenance_window: MaintenanceWindow) -> Self {
         let mut builder = self;
@@ -614,12 +1380,43 @@ impl DeploymentBuilder {
             version: version.to_string(),
             name: name.to_string(),
             artifacts,
+            metadata: Vec::new(),
+            id: None,
         };
         builder.chunks.push(chunk);
 
         builder
     }
 
+    /// Set `id` as the software module id of the chunk named `chunk`, which must
+    /// have been added first.
+    pub fn chunk_id(self, chunk: &str, id: u64) -> Self {
+        let mut builder = self;
+
+        let chunk = builder
+            .chunks
+            .iter_mut()
+            .find(|c| c.name == chunk)
+            .expect("unknown chunk");
+        chunk.id = Some(id);
+
+        builder
+    }
+
+    /// Add a metadata to the chunk named `chunk`, which must have been added first.
+    pub fn chunk_metadata(self, chunk: &str, key: &str, value: &str) -> Self {
+        let mut builder = self;
+
+        let chunk = builder
+            .chunks
+            .iter_mut()
+            .find(|c| c.name == chunk)
+            .expect("unknown chunk");
+        chunk.metadata.push((key.to_string(), value.to_string()));
+
+        builder
+    }
+
     /// Create the [`Deployment`].
     pub fn build(self) -> Deployment {
         Deployment {
@@ -628,6 +1425,10 @@ impl DeploymentBuilder {
             update_type: self.update_type,
             maintenance_window: self.maintenance_window,
             chunks: self.chunks,
+            range_requests: self.range_requests,
+            download_delay: self.download_delay,
+            overrides: self.overrides,
+            action_history: self.action_history,
         }
     }
 }
@@ -661,10 +1462,35 @@ pub struct Chunk {
     version: String,
     name: String,
     artifacts: Vec<(PathBuf, String, String, String)>, // (path, md5, sha1, sha256)
+    metadata: Vec<(String, String)>,                   // (key, value)
+    id: Option<u64>,
 }
 
 impl Chunk {
-    fn json(&self, base_url: &str) -> serde_json::Value {
+    fn json(&self, base_url: &str, overrides: &ArtifactOverrides) -> serde_json::Value {
+        let mut chunk = json!({
+            "part": self.part,
+            "version": self.version,
+            "name": self.name,
+            "artifacts": self.artifacts_json(base_url, overrides),
+        });
+
+        if let Some(id) = self.id {
+            chunk["id"] = json!(id);
+        }
+        if !self.metadata.is_empty() {
+            let metadata: Vec<serde_json::Value> = self
+                .metadata
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "value": value }))
+                .collect();
+            chunk["metadata"] = json!(metadata);
+        }
+
+        chunk
+    }
+
+    fn artifacts_json(&self, base_url: &str, overrides: &ArtifactOverrides) -> serde_json::Value {
         let artifacts: Vec<serde_json::Value> = self
             .artifacts
             .iter()
@@ -672,12 +1498,15 @@ impl Chunk {
                 let meta = path.metadata().unwrap();
                 let file_name = path.file_name().unwrap().to_str().unwrap();
                 let download_url = format!("{}/{}", base_url, file_name);
-                // TODO: the md5 url is not served by the http server
                 let md5_url = format!("{}.MD5SUM", download_url);
 
                 let mut links = serde_json::Map::new();
 
                 if self.protocol.https() {
+                    let https_base_url = overrides.https_base_url.as_deref().unwrap_or(base_url);
+                    let download_url = format!("{}/{}", https_base_url, file_name);
+                    let md5_url = format!("{}.MD5SUM", download_url);
+
                     links.insert("download".to_string(), json!({ "href": download_url }));
                     links.insert("md5sum".to_string(), json!({ "href": md5_url }));
                 }
@@ -686,31 +1515,36 @@ impl Chunk {
                     links.insert("md5sum-http".to_string(), json!({ "href": md5_url }));
                 }
 
+                let filename = overrides
+                    .filenames
+                    .get(path)
+                    .map_or(file_name, |f| f.as_str());
+                let size = overrides.sizes.get(path).copied().unwrap_or(meta.len());
+
                 json!({
-                    "filename": file_name,
+                    "filename": filename,
                     "hashes": {
                         "sha1": sha1,
                         "md5": md5,
                         "sha256": sha256,
                     },
-                    "size": meta.len(),
+                    "size": size,
                     "_links": links,
                 })
             })
             .collect();
 
-        json!({
-            "part": self.part,
-            "version": self.version,
-            "name": self.name,
-            "artifacts": artifacts,
-        })
+        json!(artifacts)
     }
 }
 
 impl Deployment {
     fn json(&self, base_url: &str) -> serde_json::Value {
-        let chunks: Vec<serde_json::Value> = self.chunks.iter().map(|c| c.json(base_url)).collect();
+        let chunks: Vec<serde_json::Value> = self
+            .chunks
+            .iter()
+            .map(|c| c.json(base_url, &self.overrides))
+            .collect();
 
         let mut j = json!({
             "id": self.id,
@@ -728,4 +1562,13 @@ impl Deployment {
 
         j
     }
+
+    fn action_history_json(&self) -> Option<serde_json::Value> {
+        self.action_history.as_ref().map(|(status, messages)| {
+            json!({
+                "status": status,
+                "messages": messages,
+            })
+        })
+    }
 }
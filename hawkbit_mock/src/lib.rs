@@ -13,3 +13,5 @@
 //! is implemented, see the [`ddi`] module.
 
 pub mod ddi;
+#[cfg(feature = "tls")]
+mod tls;
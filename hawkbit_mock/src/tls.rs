@@ -0,0 +1,124 @@
+// Copyright 2021, Collabora Ltd.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+// TLS front-end forwarding the connections to the mock HTTP server
+
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread;
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
+use tokio_rustls::rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+/// PEM encoded TLS settings of the server.
+pub(crate) struct TlsConfig {
+    pub(crate) certificate: Vec<u8>,
+    pub(crate) key: Vec<u8>,
+    pub(crate) client_ca: Option<Vec<u8>>,
+}
+
+impl TlsConfig {
+    fn server_config(&self) -> ServerConfig {
+        let certificates = rustls_pemfile::certs(&mut BufReader::new(self.certificate.as_slice()))
+            .expect("invalid certificate")
+            .into_iter()
+            .map(Certificate)
+            .collect();
+        let key = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(self.key.as_slice()))
+            .expect("invalid key")
+            .pop()
+            .expect("missing PKCS#8 key");
+
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &self.client_ca {
+            Some(client_ca) => {
+                let mut roots = RootCertStore::empty();
+                let cas = rustls_pemfile::certs(&mut BufReader::new(client_ca.as_slice()))
+                    .expect("invalid client CA");
+                roots.add_parsable_certificates(&cas);
+
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        builder
+            .with_single_cert(certificates, PrivateKey(key))
+            .expect("invalid TLS configuration")
+    }
+}
+
+/// Accept TLS connections and forward their decrypted content to `backend`.
+pub(crate) struct TlsProxy {
+    address: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl TlsProxy {
+    pub(crate) fn start(config: &TlsConfig, backend: SocketAddr) -> Self {
+        let acceptor = TlsAcceptor::from(Arc::new(config.server_config()));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let address = listener.local_addr().unwrap();
+        let (shutdown, shutdown_rx) = oneshot::channel();
+
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_io()
+                .build()
+                .unwrap();
+
+            runtime.block_on(async move {
+                let listener = TcpListener::from_std(listener).unwrap();
+
+                let accept = async move {
+                    loop {
+                        let stream = match listener.accept().await {
+                            Ok((stream, _)) => stream,
+                            Err(_) => continue,
+                        };
+                        let acceptor = acceptor.clone();
+
+                        tokio::spawn(async move {
+                            // Failed handshakes, such as a client not providing a valid
+                            // certificate, simply drop the connection.
+                            if let Ok(mut stream) = acceptor.accept(stream).await {
+                                if let Ok(mut backend) = TcpStream::connect(backend).await {
+                                    let _ =
+                                        tokio::io::copy_bidirectional(&mut stream, &mut backend)
+                                            .await;
+                                }
+                            }
+                        });
+                    }
+                };
+
+                tokio::select! {
+                    _ = accept => {},
+                    _ = shutdown_rx => {},
+                }
+            });
+        });
+
+        Self {
+            address,
+            shutdown: Some(shutdown),
+        }
+    }
+
+    pub(crate) fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for TlsProxy {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}